@@ -0,0 +1,43 @@
+//! Run with `--features prefetch`, without it `get_batched` doesn't
+//! prefetch and is expected to be on par with sequential lookups.
+use criterion::*;
+
+const PROBES: u64 = 64;
+
+fn lookups(c: &mut Criterion) {
+    for entries in [10_000, 4_000_000] {
+        let map: halfbrown::HashMap<u64, u64> = (0..entries).map(|i| (i, i)).collect();
+        // spread the probes over the whole map and miss every fourth one,
+        // every batch uses different keys so they aren't cached from the
+        // previous one
+        let batches: Vec<Vec<u64>> = (0..1024)
+            .map(|b| {
+                (0..PROBES)
+                    .map(|i| (i * 7919 + b * 104_729) % entries + (i % 4) * entries)
+                    .collect()
+            })
+            .collect();
+        let batches: Vec<Vec<&u64>> = batches.iter().map(|b| b.iter().collect()).collect();
+
+        let mut group = c.benchmark_group(format!("get({}) of {}", PROBES, entries));
+        group.bench_function("sequential", |b| {
+            let mut batch = batches.iter().cycle();
+            b.iter(|| {
+                batch
+                    .next()
+                    .into_iter()
+                    .flatten()
+                    .map(|k| map.get(*k))
+                    .collect::<Vec<Option<&u64>>>()
+            })
+        });
+        group.bench_function("batched", |b| {
+            let mut batch = batches.iter().cycle();
+            b.iter(|| batch.next().map(|refs| map.get_batched(black_box(refs))))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(batched, lookups);
+criterion_main!(batched);
@@ -1,6 +1,6 @@
+#![allow(deprecated)]
+
 use criterion::*;
-use halfbrown;
-use hashbrown;
 use std::collections;
 
 const NAMES: [&str; 162] = [
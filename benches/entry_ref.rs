@@ -0,0 +1,31 @@
+use criterion::*;
+
+fn hits(c: &mut Criterion) {
+    for n in [16, 100] {
+        let keys: Vec<String> = (0..n).map(|i| format!("key{}", i)).collect();
+        let mut map: halfbrown::HashMap<String, usize> = halfbrown::HashMap::new();
+        for k in &keys {
+            map.insert(k.clone(), 0);
+        }
+
+        let mut group = c.benchmark_group(format!("1000 hits on {} keys", n));
+        group.bench_function("entry_ref", |b| {
+            b.iter(|| {
+                for i in 0..1000 {
+                    *map.entry_ref(black_box(keys[i % n].as_str())).or_insert(0) += 1;
+                }
+            })
+        });
+        group.bench_function("entry", |b| {
+            b.iter(|| {
+                for i in 0..1000 {
+                    *map.entry(black_box(keys[i % n].to_owned())).or_insert(0) += 1;
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(entry_ref, hits);
+criterion_main!(entry_ref);
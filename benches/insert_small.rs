@@ -0,0 +1,43 @@
+use criterion::*;
+
+/// The vector backend's insert without the promotion check, as a baseline
+/// for what the check costs.
+fn vec_insert(v: &mut Vec<(usize, usize)>, k: usize, value: usize) -> Option<usize> {
+    for (key, old) in v.iter_mut() {
+        if *key == k {
+            return Some(std::mem::replace(old, value));
+        }
+    }
+    v.push((k, value));
+    None
+}
+
+fn insert_4(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert into a map of 4");
+    group.bench_function("halfbrown", |b| {
+        b.iter_batched_ref(
+            || halfbrown::HashMap::vec_with_capacity(4),
+            |m| {
+                for i in 0..16 {
+                    m.insert(black_box(i % 4), i);
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("vec (no check)", |b| {
+        b.iter_batched_ref(
+            || Vec::with_capacity(4),
+            |m| {
+                for i in 0..16 {
+                    vec_insert(m, black_box(i % 4), i);
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(insert_small, insert_4);
+criterion_main!(insert_small);
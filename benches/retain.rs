@@ -0,0 +1,35 @@
+use criterion::*;
+
+fn retain_half(c: &mut Criterion) {
+    let mut map = halfbrown::HashMap::new();
+    for i in 0..halfbrown::VEC_LIMIT_UPPER {
+        map.insert(i, i);
+    }
+
+    let mut group = c.benchmark_group("retain half of 32");
+    group.bench_function("halfbrown", |b| {
+        b.iter_batched(
+            || map.clone(),
+            |mut m| {
+                m.retain(|k, _| k % 2 == 0);
+                m
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    let map: hashbrown::HashMap<usize, usize> = map.iter().map(|(k, v)| (*k, *v)).collect();
+    group.bench_function("hashbrown", |b| {
+        b.iter_batched(
+            || map.clone(),
+            |mut m| {
+                m.retain(|k, _| k % 2 == 0);
+                m
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(retain, retain_half);
+criterion_main!(retain);
@@ -3,11 +3,9 @@
 //! their copyright.
 
 use crate::vecmap::{self, Entry as VecMapEntry};
+use crate::{HashMap, HashMapInt};
 use core::hash::{BuildHasher, Hash};
-use hashbrown::{
-    self,
-    hash_map::{self, Entry as HashBrownEntry},
-};
+use hashbrown::hash_map::{RawEntryMut, RawOccupiedEntryMut, RawVacantEntryMut};
 use std::fmt;
 
 /////// General
@@ -61,9 +59,46 @@ where
         }
     }
 
+    /// Ensures a value is in the entry by inserting the default if empty, and returns
+    /// a mutable reference to the value in the entry together with the length of the
+    /// map after the operation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<&str, u32> = HashMap::new();
+    ///
+    /// let (v, len) = map.entry("poneyland").or_insert_tracked(3);
+    /// assert_eq!((*v, len), (3, 1));
+    ///
+    /// let (v, len) = map.entry("poneyland").or_insert_tracked(10);
+    /// assert_eq!((*v, len), (3, 1));
+    /// ```
+    #[inline]
+    pub fn or_insert_tracked(self, default: V) -> (&'a mut V, usize)
+    where
+        K: Hash,
+    {
+        match self {
+            Entry::Occupied(entry) => {
+                let len = entry.map_len();
+                (entry.into_mut(), len)
+            }
+            Entry::Vacant(entry) => {
+                let len = entry.map_len() + 1;
+                (entry.insert(default), len)
+            }
+        }
+    }
+
     /// Ensures a value is in the entry by inserting the result of the default function if empty,
     /// and returns a mutable reference to the value in the entry.
     ///
+    /// The default function is called before anything is inserted, if it
+    /// panics the map is left unchanged.
+    ///
     /// # Examples
     ///
     /// ```
@@ -127,6 +162,7 @@ where
     /// assert_eq!(map["poneyland"], 43);
     /// ```
     #[inline]
+    #[must_use]
     pub fn and_modify<F>(self, f: F) -> Self
     where
         F: FnOnce(&mut V),
@@ -139,16 +175,135 @@ where
             Entry::Vacant(entry) => Entry::Vacant(entry),
         }
     }
+
+    /// Provides in-place mutable access to an occupied entry, along with its
+    /// key, before any potential inserts into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<&str, usize> = HashMap::new();
+    /// map.insert("poneyland", 2);
+    ///
+    /// map.entry("poneyland")
+    ///    .and_modify_with_key(|k, e| { *e *= k.len() })
+    ///    .or_insert(42);
+    /// assert_eq!(map["poneyland"], 18);
+    ///
+    /// map.entry("horseland")
+    ///    .and_modify_with_key(|k, e| { *e *= k.len() })
+    ///    .or_insert(42);
+    /// assert_eq!(map["horseland"], 42);
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn and_modify_with_key<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&K, &mut V),
+    {
+        match self {
+            Entry::Occupied(mut entry) => {
+                let (k, v) = entry.key_value_mut();
+                f(k, v);
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+
+    /// Provides in-place mutable access to an occupied entry and removes it
+    /// from the map if `pred` returns `true`, turning it into a vacant
+    /// entry. Vacant entries are left as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<&str, u32> = HashMap::new();
+    /// map.insert("poneyland", 2);
+    ///
+    /// let decrement = |e: &mut u32| {
+    ///     *e -= 1;
+    ///     *e == 0
+    /// };
+    ///
+    /// map.entry("poneyland").and_remove_if(decrement);
+    /// assert_eq!(map["poneyland"], 1);
+    ///
+    /// map.entry("poneyland").and_remove_if(decrement);
+    /// assert!(!map.contains_key("poneyland"));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics for a map backed entry that wraps an [`OccupiedEntry`] returned
+    /// by [`VacantEntry::insert_entry`] if it gets removed, as that holds no
+    /// key for the vacant entry.
+    #[inline]
+    #[must_use]
+    pub fn and_remove_if<F>(self, pred: F) -> Self
+    where
+        F: FnOnce(&mut V) -> bool,
+    {
+        match self {
+            Entry::Occupied(OccupiedEntry(OccupiedEntryInt::Map {
+                mut entry,
+                key,
+                hash,
+                len,
+            })) => {
+                if !pred(entry.get_mut()) {
+                    return Entry::Occupied(OccupiedEntry(OccupiedEntryInt::Map {
+                        entry,
+                        key,
+                        hash,
+                        len,
+                    }));
+                }
+                // raw entries drop the stored key with the value, so swap
+                // in the key the entry was created with to drop instead
+                let key = entry.insert_key(key.expect("entry was not created with a key"));
+                match entry.replace_entry_with(|_, _| None) {
+                    RawEntryMut::Vacant(entry) => Entry::Vacant(VacantEntry(VacantEntryInt::Map {
+                        entry,
+                        key,
+                        hash,
+                        len: len - 1,
+                    })),
+                    RawEntryMut::Occupied(_) => unreachable!(),
+                }
+            }
+            Entry::Occupied(OccupiedEntry(OccupiedEntryInt::Vec(entry))) => {
+                entry.remove_if(pred).into()
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
 }
 
-impl<'a, K, V, S> From<HashBrownEntry<'a, K, V, S>> for Entry<'a, K, V, S>
+impl<'a, K, V, S> Entry<'a, K, V, S>
 where
     S: BuildHasher,
 {
-    fn from(f: HashBrownEntry<'a, K, V, S>) -> Entry<'a, K, V, S> {
+    /// Wraps a raw entry of a map backed map holding `len` elements, looked
+    /// up for `key` by its `hash`.
+    pub(crate) fn from_map(f: RawEntryMut<'a, K, V, S>, key: K, hash: u64, len: usize) -> Self {
         match f {
-            HashBrownEntry::Occupied(o) => Entry::Occupied(OccupiedEntry(OccupiedEntryInt::Map(o))),
-            HashBrownEntry::Vacant(o) => Entry::Vacant(VacantEntry(VacantEntryInt::Map(o))),
+            RawEntryMut::Occupied(entry) => Entry::Occupied(OccupiedEntry(OccupiedEntryInt::Map {
+                entry,
+                key: Some(key),
+                hash,
+                len,
+            })),
+            RawEntryMut::Vacant(entry) => Entry::Vacant(VacantEntry(VacantEntryInt::Map {
+                entry,
+                key,
+                hash,
+                len,
+            })),
         }
     }
 }
@@ -189,7 +344,16 @@ enum OccupiedEntryInt<'a, K, V, S>
 where
     S: BuildHasher,
 {
-    Map(hash_map::OccupiedEntry<'a, K, V, S>),
+    /// a map based implementation, hashbrowns raw entries are used so the
+    /// key and value can be borrowed together. Like hashbrowns own entries
+    /// we keep the key the entry was created with and its hash, next to
+    /// them the length of the map as the entries don't expose the map
+    Map {
+        entry: RawOccupiedEntryMut<'a, K, V, S>,
+        key: Option<K>,
+        hash: u64,
+        len: usize,
+    },
     Vec(vecmap::OccupiedEntry<'a, K, V, S>),
 }
 
@@ -214,8 +378,12 @@ where
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.0 {
-            OccupiedEntryInt::Map(m) => write!(f, "{:?}", m),
-            OccupiedEntryInt::Vec(m) => write!(f, "{:?}", m),
+            OccupiedEntryInt::Map { entry, .. } => f
+                .debug_struct("OccupiedEntry")
+                .field("key", entry.key())
+                .field("value", entry.get())
+                .finish(),
+            OccupiedEntryInt::Vec(m) => write!(f, "{m:?}"),
         }
     }
 }
@@ -232,20 +400,39 @@ enum VacantEntryInt<'a, K, V, S>
 where
     S: BuildHasher,
 {
-    /// a map based implementation
-    Map(hashbrown::hash_map::VacantEntry<'a, K, V, S>),
+    /// a map based implementation, the raw entry together with the key and
+    /// its hash, next to them the length of the map
+    Map {
+        entry: RawVacantEntryMut<'a, K, V, S>,
+        key: K,
+        hash: u64,
+        len: usize,
+    },
     /// a vec based implementation
     Vec(vecmap::VacantEntry<'a, K, V, S>),
+    /// a vec based implementation that is full, inserting into it promotes
+    /// the map to the map backend first
+    Promote {
+        key: K,
+        map: &'a mut HashMap<K, V, S>,
+        /// `VacantEntry::insert` doesn't require the bounds promotion needs,
+        /// so we capture the function doing it when creating the entry
+        insert: PromoteInsert<'a, K, V, S>,
+    },
 }
 
+type PromoteInsert<'a, K, V, S> = fn(&'a mut HashMap<K, V, S>, K, V) -> OccupiedEntry<'a, K, V, S>;
+
 impl<K: fmt::Debug, V, S> fmt::Debug for VacantEntry<'_, K, V, S>
 where
     S: BuildHasher,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.0 {
-            VacantEntryInt::Map(m) => write!(f, "{:?}", m),
-            VacantEntryInt::Vec(m) => write!(f, "{:?}", m),
+            VacantEntryInt::Map { key, .. } | VacantEntryInt::Promote { key, .. } => {
+                f.debug_tuple("VacantEntry").field(key).finish()
+            }
+            VacantEntryInt::Vec(m) => write!(f, "{m:?}"),
         }
     }
 }
@@ -268,11 +455,73 @@ where
     #[inline]
     pub fn key(&self) -> &K {
         match &self.0 {
-            OccupiedEntryInt::Map(m) => m.key(),
+            OccupiedEntryInt::Map { entry, .. } => entry.key(),
             OccupiedEntryInt::Vec(m) => m.key(),
         }
     }
 
+    /// The number of elements in the map the entry points into.
+    fn map_len(&self) -> usize {
+        match &self.0 {
+            OccupiedEntryInt::Map { len, .. } => *len,
+            OccupiedEntryInt::Vec(m) => m.map_len(),
+        }
+    }
+
+    /// Gets a reference to the key and a mutable reference to the value in
+    /// the entry.
+    #[inline]
+    fn key_value_mut(&mut self) -> (&K, &mut V) {
+        match &mut self.0 {
+            OccupiedEntryInt::Map { entry, .. } => {
+                let (k, v) = entry.get_key_value_mut();
+                (k, v)
+            }
+            OccupiedEntryInt::Vec(m) => m.key_value_mut(),
+        }
+    }
+
+    /// Converts the entry into a reference to the key and a mutable
+    /// reference to the value with a lifetime bound to the map itself.
+    #[inline]
+    fn into_key_value(self) -> (&'a K, &'a mut V) {
+        match self.0 {
+            OccupiedEntryInt::Map { entry, .. } => {
+                let (k, v) = entry.into_key_value();
+                (k, v)
+            }
+            OccupiedEntryInt::Vec(m) => m.into_key_value(),
+        }
+    }
+
+    /// Gets the position of the entry if the map is vector backed, for map
+    /// backed maps `None` is returned.
+    ///
+    /// The position is only valid until the map is next modified, removing
+    /// or inserting elements may move entries around.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// use halfbrown::Entry;
+    ///
+    /// let mut map: HashMap<&str, u32> = HashMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("poneyland", 12);
+    ///
+    /// if let Entry::Occupied(o) = map.entry("poneyland") {
+    ///     assert_eq!(o.index(), Some(1));
+    /// }
+    /// ```
+    #[inline]
+    pub fn index(&self) -> Option<usize> {
+        match &self.0 {
+            OccupiedEntryInt::Map { .. } => None,
+            OccupiedEntryInt::Vec(m) => Some(m.index()),
+        }
+    }
+
     /// Take the ownership of the key and value from the map.
     ///
     /// # Examples
@@ -294,7 +543,7 @@ where
     #[inline]
     pub fn remove_entry(self) -> (K, V) {
         match self.0 {
-            OccupiedEntryInt::Map(m) => m.remove_entry(),
+            OccupiedEntryInt::Map { entry, .. } => entry.remove_entry(),
             OccupiedEntryInt::Vec(m) => m.remove_entry(),
         }
     }
@@ -317,11 +566,31 @@ where
     #[inline]
     pub fn get(&self) -> &V {
         match &self.0 {
-            OccupiedEntryInt::Map(m) => m.get(),
+            OccupiedEntryInt::Map { entry, .. } => entry.get(),
             OccupiedEntryInt::Vec(m) => m.get(),
         }
     }
 
+    /// Gets a reference to the stored key and the value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// use halfbrown::Entry;
+    ///
+    /// let mut map: HashMap<&str, u32> = HashMap::new();
+    /// map.entry("poneyland").or_insert(12);
+    ///
+    /// if let Entry::Occupied(o) = map.entry("poneyland") {
+    ///     assert_eq!(o.key_value(), (&"poneyland", &12));
+    /// }
+    /// ```
+    #[inline]
+    pub fn key_value(&self) -> (&K, &V) {
+        (self.key(), self.get())
+    }
+
     /// Gets a mutable reference to the value in the entry.
     ///
     /// If you need a reference to the `OccupiedEntry` which may outlive the
@@ -352,7 +621,7 @@ where
     #[inline]
     pub fn get_mut(&mut self) -> &mut V {
         match &mut self.0 {
-            OccupiedEntryInt::Map(m) => m.get_mut(),
+            OccupiedEntryInt::Map { entry, .. } => entry.get_mut(),
             OccupiedEntryInt::Vec(m) => m.get_mut(),
         }
     }
@@ -383,7 +652,7 @@ where
     #[inline]
     pub fn into_mut(self) -> &'a mut V {
         match self.0 {
-            OccupiedEntryInt::Map(m) => m.into_mut(),
+            OccupiedEntryInt::Map { entry, .. } => entry.into_mut(),
             OccupiedEntryInt::Vec(m) => m.into_mut(),
         }
     }
@@ -408,7 +677,7 @@ where
     #[inline]
     pub fn insert(&mut self, value: V) -> V {
         match &mut self.0 {
-            OccupiedEntryInt::Map(m) => m.insert(value),
+            OccupiedEntryInt::Map { entry, .. } => entry.insert(value),
             OccupiedEntryInt::Vec(m) => m.insert(value),
         }
     }
@@ -433,11 +702,50 @@ where
     #[inline]
     pub fn remove(self) -> V {
         match self.0 {
-            OccupiedEntryInt::Map(m) => m.remove(),
+            OccupiedEntryInt::Map { entry, .. } => entry.remove(),
             OccupiedEntryInt::Vec(m) => m.remove(),
         }
     }
 
+    /// Replaces the value of the entry with the one computed from the old
+    /// value by `f`, and returns a mutable reference to it with a lifetime
+    /// bound to the map itself.
+    ///
+    /// The old value is moved into `f`, if `f` panics there is no value left
+    /// to keep so the entry is removed from the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// use halfbrown::Entry;
+    ///
+    /// let mut map: HashMap<&str, Vec<u32>> = HashMap::new();
+    /// map.insert("poneyland", vec![1, 2]);
+    ///
+    /// if let Entry::Occupied(o) = map.entry("poneyland") {
+    ///     let v = o.replace_with(|v| v.into_iter().rev().collect());
+    ///     v.push(0);
+    /// }
+    ///
+    /// assert_eq!(map["poneyland"], vec![2, 1, 0]);
+    /// ```
+    #[inline]
+    pub fn replace_with<F>(self, f: F) -> &'a mut V
+    where
+        F: FnOnce(V) -> V,
+    {
+        match self.0 {
+            OccupiedEntryInt::Map { entry, .. } => {
+                match entry.replace_entry_with(|_, v| Some(f(v))) {
+                    RawEntryMut::Occupied(o) => o.into_mut(),
+                    RawEntryMut::Vacant(_) => unreachable!(),
+                }
+            }
+            OccupiedEntryInt::Vec(m) => m.replace_with(f),
+        }
+    }
+
     /// Replaces the entry, returning the old key and value. The new key in the hash map will be
     /// the key used to create this entry.
     ///
@@ -458,10 +766,18 @@ where
     /// }
     ///
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics for an entry returned by [`VacantEntry::insert_entry`], as that
+    /// holds no key to replace the stored one with.
     #[inline]
     pub fn replace_entry(self, value: V) -> (K, V) {
         match self.0 {
-            OccupiedEntryInt::Map(m) => m.replace_entry(value),
+            OccupiedEntryInt::Map { mut entry, key, .. } => {
+                let key = entry.insert_key(key.expect("entry was not created with a key"));
+                (key, entry.insert(value))
+            }
             OccupiedEntryInt::Vec(m) => m.replace_entry(value),
         }
     }
@@ -490,10 +806,17 @@ where
     ///     }
     /// }
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics for an entry returned by [`VacantEntry::insert_entry`], as that
+    /// holds no key to replace the stored one with.
     #[inline]
     pub fn replace_key(self) -> K {
         match self.0 {
-            OccupiedEntryInt::Map(m) => m.replace_key(),
+            OccupiedEntryInt::Map { mut entry, key, .. } => {
+                entry.insert_key(key.expect("entry was not created with a key"))
+            }
             OccupiedEntryInt::Vec(m) => m.replace_key(),
         }
     }
@@ -517,7 +840,7 @@ where
     #[inline]
     pub fn key(&self) -> &K {
         match &self.0 {
-            VacantEntryInt::Map(m) => m.key(),
+            VacantEntryInt::Map { key, .. } | VacantEntryInt::Promote { key, .. } => key,
             VacantEntryInt::Vec(m) => m.key(),
         }
     }
@@ -539,7 +862,7 @@ where
     #[inline]
     pub fn into_key(self) -> K {
         match self.0 {
-            VacantEntryInt::Map(m) => m.into_key(),
+            VacantEntryInt::Map { key, .. } | VacantEntryInt::Promote { key, .. } => key,
             VacantEntryInt::Vec(m) => m.into_key(),
         }
     }
@@ -567,8 +890,143 @@ where
         S: BuildHasher,
     {
         match self.0 {
-            VacantEntryInt::Map(m) => m.insert(value),
+            VacantEntryInt::Map {
+                entry, key, hash, ..
+            } => entry.insert_hashed_nocheck(hash, key, value).1,
             VacantEntryInt::Vec(m) => m.insert(value),
+            VacantEntryInt::Promote { key, map, insert } => insert(map, key, value).into_mut(),
+        }
+    }
+
+    /// Sets the value of the entry with the `VacantEntry`'s key,
+    /// and returns an `OccupiedEntry` for it.
+    ///
+    /// The returned entry no longer holds a key of its own, so calling
+    /// [`OccupiedEntry::replace_entry`] or [`OccupiedEntry::replace_key`]
+    /// on it will panic.
+    ///
+    /// Unlike [`insert`] this hashes the key a second time for map backed
+    /// maps, hashbrown only turns a vacant raw entry into an occupied one
+    /// by hashing the key it is given.
+    ///
+    /// [`insert`]: #method.insert
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// use halfbrown::Entry;
+    ///
+    /// let mut map: HashMap<&str, u32> = HashMap::new();
+    ///
+    /// if let Entry::Vacant(v) = map.entry("poneyland") {
+    ///     let o = v.insert_entry(37);
+    ///     assert_eq!(o.get(), &37);
+    ///     assert_eq!(o.remove_entry(), ("poneyland", 37));
+    /// }
+    /// assert!(map.is_empty());
+    /// ```
+    #[inline]
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S>
+    where
+        K: Hash,
+    {
+        match self.0 {
+            VacantEntryInt::Map {
+                entry,
+                key,
+                hash,
+                len,
+            } => OccupiedEntry(OccupiedEntryInt::Map {
+                entry: RawEntryMut::Vacant(entry).insert(key, value),
+                key: None,
+                hash,
+                len: len + 1,
+            }),
+            VacantEntryInt::Vec(m) => OccupiedEntry(OccupiedEntryInt::Vec(m.insert_entry(value))),
+            VacantEntryInt::Promote { key, map, insert } => insert(map, key, value),
+        }
+    }
+
+    /// Sets the value of the entry with the `VacantEntry`'s key, and returns
+    /// references to the stored key and value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// use halfbrown::Entry;
+    ///
+    /// let mut map: HashMap<String, u32> = HashMap::new();
+    ///
+    /// if let Entry::Vacant(v) = map.entry("poneyland".to_string()) {
+    ///     let (k, v) = v.insert_key_value(37);
+    ///     *v += k.len() as u32;
+    ///     assert_eq!(k, "poneyland");
+    /// }
+    /// assert_eq!(map["poneyland"], 46);
+    /// ```
+    #[inline]
+    pub fn insert_key_value(self, value: V) -> (&'a K, &'a mut V)
+    where
+        K: Hash,
+    {
+        match self.0 {
+            VacantEntryInt::Map {
+                entry, key, hash, ..
+            } => {
+                let (k, v) = entry.insert_hashed_nocheck(hash, key, value);
+                (k, v)
+            }
+            _ => self.insert_entry(value).into_key_value(),
+        }
+    }
+
+    /// The number of elements in the map the entry points into.
+    fn map_len(&self) -> usize {
+        match &self.0 {
+            VacantEntryInt::Map { len, .. } => *len,
+            VacantEntryInt::Vec(m) => m.map_len(),
+            VacantEntryInt::Promote { map, .. } => map.len(),
+        }
+    }
+
+    /// Creates a vacant entry for a full vector backed map, the map is
+    /// promoted once a value is inserted.
+    pub(crate) fn promoting(key: K, map: &'a mut HashMap<K, V, S>) -> Self
+    where
+        K: Eq + Hash,
+    {
+        VacantEntry(VacantEntryInt::Promote {
+            key,
+            map,
+            insert: Self::promote_insert,
+        })
+    }
+
+    /// Promotes the map the same unwind safe way `HashMap::insert` does, if
+    /// hashing panics the map stays vector backed.
+    fn promote_insert(map: &'a mut HashMap<K, V, S>, key: K, value: V) -> OccupiedEntry<'a, K, V, S>
+    where
+        K: Eq + Hash,
+    {
+        map.swap_to_map(1);
+        match &mut map.0 {
+            HashMapInt::Map(m) => {
+                let len = m.len() + 1;
+                let hash = m.hasher().hash_one(&key);
+                OccupiedEntry(OccupiedEntryInt::Map {
+                    entry: m
+                        .raw_entry_mut()
+                        .from_hash(hash, |_| false)
+                        .insert(key, value),
+                    key: None,
+                    hash,
+                    len,
+                })
+            }
+            HashMapInt::Vec(_) => unreachable!(),
+            HashMapInt::None => none_state!(),
         }
     }
 }
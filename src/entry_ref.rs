@@ -0,0 +1,353 @@
+//! Entries looked up by a borrowed form of the key, the owned key is
+//! only created when a vacant entry is actually inserted into.
+//!
+//! Note: Most of the documentation is taken from
+//! rusts hashmap.rs and should be considered under
+//! their copyright.
+
+use crate::{RawOccupiedEntryMut, RawVacantEntryMut};
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use std::fmt;
+
+/// A view into a single entry in a map, which may either be vacant or occupied,
+/// that was looked up by a borrowed key.
+///
+/// This `enum` is constructed from the [`entry_ref`] method on [`HashMap`].
+///
+/// [`HashMap`]: struct.HashMap.html
+/// [`entry_ref`]: struct.HashMap.html#method.entry_ref
+pub enum EntryRef<'a, 'b, K, Q: ?Sized, V, S> {
+    /// An occupied entry.
+    Occupied(OccupiedEntryRef<'a, K, V, S>),
+
+    /// A vacant entry.
+    Vacant(VacantEntryRef<'a, 'b, K, Q, V, S>),
+}
+
+impl<'a, 'b, K, Q: ?Sized, V, S> EntryRef<'a, 'b, K, Q, V, S>
+where
+    S: BuildHasher,
+{
+    /// Returns a reference to the borrowed key for vacant entries and
+    /// the stored key for occupied ones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<String, u32> = HashMap::new();
+    /// assert_eq!(map.entry_ref("poneyland").key(), "poneyland");
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> &Q
+    where
+        K: Borrow<Q>,
+    {
+        match self {
+            EntryRef::Occupied(entry) => entry.key().borrow(),
+            EntryRef::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the default if empty, and returns
+    /// a mutable reference to the value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<String, u32> = HashMap::new();
+    ///
+    /// map.entry_ref("poneyland").or_insert(3);
+    /// assert_eq!(map["poneyland"], 3);
+    ///
+    /// *map.entry_ref("poneyland").or_insert(10) *= 2;
+    /// assert_eq!(map["poneyland"], 6);
+    /// ```
+    #[inline]
+    pub fn or_insert(self, default: V) -> &'a mut V
+    where
+        K: Hash + From<&'b Q>,
+    {
+        match self {
+            EntryRef::Occupied(entry) => entry.into_mut(),
+            EntryRef::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the default function if empty,
+    /// and returns a mutable reference to the value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<String, String> = HashMap::new();
+    /// let s = "hoho".to_string();
+    ///
+    /// map.entry_ref("poneyland").or_insert_with(|| s);
+    ///
+    /// assert_eq!(map["poneyland"], "hoho".to_string());
+    /// ```
+    #[inline]
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V
+    where
+        K: Hash + From<&'b Q>,
+    {
+        match self {
+            EntryRef::Occupied(entry) => entry.into_mut(),
+            EntryRef::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the default function
+    /// if empty, and returns a mutable reference to the value in the entry.
+    ///
+    /// The default function is given the borrowed key the entry was looked up
+    /// with, the owned key is only created once the value is inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<String, usize> = HashMap::new();
+    ///
+    /// for word in "the quick brown fox jumps over the lazy dog".split(' ') {
+    ///     map.entry_ref(word).or_insert_with_key(|key| key.len());
+    /// }
+    ///
+    /// assert_eq!(map.len(), 8);
+    /// assert_eq!(map["quick"], 5);
+    /// assert_eq!(map["the"], 3);
+    /// ```
+    #[inline]
+    pub fn or_insert_with_key<F: FnOnce(&Q) -> V>(self, default: F) -> &'a mut V
+    where
+        K: Hash + From<&'b Q>,
+    {
+        match self {
+            EntryRef::Occupied(entry) => entry.into_mut(),
+            EntryRef::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the default value if empty,
+    /// and returns a mutable reference to the value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<String, Option<u32>> = HashMap::new();
+    /// map.entry_ref("poneyland").or_default();
+    ///
+    /// assert_eq!(map["poneyland"], None);
+    /// ```
+    #[inline]
+    pub fn or_default(self) -> &'a mut V
+    where
+        K: Hash + From<&'b Q>,
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any
+    /// potential inserts into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<String, u32> = HashMap::new();
+    ///
+    /// map.entry_ref("poneyland")
+    ///    .and_modify(|e| { *e += 1 })
+    ///    .or_insert(42);
+    /// assert_eq!(map["poneyland"], 42);
+    ///
+    /// map.entry_ref("poneyland")
+    ///    .and_modify(|e| { *e += 1 })
+    ///    .or_insert(42);
+    /// assert_eq!(map["poneyland"], 43);
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn and_modify<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&mut V),
+    {
+        match self {
+            EntryRef::Occupied(mut entry) => {
+                f(entry.get_mut());
+                EntryRef::Occupied(entry)
+            }
+            EntryRef::Vacant(entry) => EntryRef::Vacant(entry),
+        }
+    }
+}
+
+impl<K: fmt::Debug, Q: ?Sized + fmt::Debug, V: fmt::Debug, S> fmt::Debug
+    for EntryRef<'_, '_, K, Q, V, S>
+where
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            EntryRef::Vacant(ref v) => f.debug_tuple("EntryRef").field(v).finish(),
+            EntryRef::Occupied(ref o) => f.debug_tuple("EntryRef").field(o).finish(),
+        }
+    }
+}
+
+/// A view into an occupied entry in a `HashMap` that was looked up by a
+/// borrowed key. It is part of the [`EntryRef`] enum.
+///
+/// [`EntryRef`]: enum.EntryRef.html
+pub struct OccupiedEntryRef<'a, K, V, S>(RawOccupiedEntryMut<'a, K, V, S>);
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for OccupiedEntryRef<'_, K, V, S>
+where
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedEntryRef")
+            .field("key", self.key())
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+impl<'a, K, V, S> OccupiedEntryRef<'a, K, V, S>
+where
+    S: BuildHasher,
+{
+    /// Gets a reference to the key in the entry.
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> &K {
+        self.0.key()
+    }
+
+    /// Gets a reference to the value in the entry.
+    #[inline]
+    #[must_use]
+    pub fn get(&self) -> &V {
+        self.0.get()
+    }
+
+    /// Gets a mutable reference to the value in the entry.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut V {
+        self.0.get_mut()
+    }
+
+    /// Converts the entry into a mutable reference to the value in the entry
+    /// with a lifetime bound to the map itself.
+    #[inline]
+    #[must_use]
+    pub fn into_mut(self) -> &'a mut V {
+        self.0.into_mut()
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    #[inline]
+    pub fn insert(&mut self, value: V) -> V {
+        self.0.insert(value)
+    }
+
+    /// Takes the value out of the entry, and returns it.
+    #[inline]
+    #[must_use]
+    pub fn remove(self) -> V {
+        self.0.remove()
+    }
+
+    /// Take the ownership of the key and value from the map.
+    #[inline]
+    #[must_use]
+    pub fn remove_entry(self) -> (K, V) {
+        self.0.remove_entry()
+    }
+}
+
+/// A view into a vacant entry in a `HashMap` that was looked up by a
+/// borrowed key. It is part of the [`EntryRef`] enum.
+///
+/// [`EntryRef`]: enum.EntryRef.html
+pub struct VacantEntryRef<'a, 'b, K, Q: ?Sized, V, S> {
+    key: &'b Q,
+    hash: Option<u64>,
+    entry: RawVacantEntryMut<'a, K, V, S>,
+}
+
+impl<K, Q: ?Sized + fmt::Debug, V, S> fmt::Debug for VacantEntryRef<'_, '_, K, Q, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VacantEntryRef").field(&self.key).finish()
+    }
+}
+
+impl<'a, 'b, K, Q: ?Sized, V, S> VacantEntryRef<'a, 'b, K, Q, V, S>
+where
+    S: BuildHasher,
+{
+    pub(crate) fn new(
+        key: &'b Q,
+        hash: Option<u64>,
+        entry: RawVacantEntryMut<'a, K, V, S>,
+    ) -> Self {
+        Self { key, hash, entry }
+    }
+
+    /// Gets a reference to the borrowed key the entry was looked up with.
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> &'b Q {
+        self.key
+    }
+
+    /// Sets the value of the entry with an owned key created from the
+    /// borrowed one, and returns a mutable reference to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// use halfbrown::EntryRef;
+    ///
+    /// let mut map: HashMap<String, u32> = HashMap::new();
+    ///
+    /// if let EntryRef::Vacant(o) = map.entry_ref("poneyland") {
+    ///     o.insert(37);
+    /// }
+    /// assert_eq!(map["poneyland"], 37);
+    /// ```
+    #[inline]
+    pub fn insert(self, value: V) -> &'a mut V
+    where
+        K: Hash + From<&'b Q>,
+    {
+        let key = K::from(self.key);
+        match self.hash {
+            Some(hash) => self.entry.insert_hashed_nocheck(hash, key, value).1,
+            None => self.entry.insert(key, value).1,
+        }
+    }
+}
+
+impl<'a, K, V, S> From<RawOccupiedEntryMut<'a, K, V, S>> for OccupiedEntryRef<'a, K, V, S> {
+    fn from(e: RawOccupiedEntryMut<'a, K, V, S>) -> Self {
+        Self(e)
+    }
+}
@@ -0,0 +1,102 @@
+//! Interning of string keys, so maps with the same keys can share their
+//! storage.
+
+use crate::HashMap;
+use core::hash::BuildHasher;
+use hashbrown::HashSet;
+use std::sync::Arc;
+
+/// A set of interned strings.
+///
+/// Interning a string returns a shared `Arc<str>` for it, equal strings
+/// interned through the same `Interner` share one allocation. Using it to
+/// create the keys of several maps lets them share the key storage, see
+/// [`HashMap::insert_interned`].
+///
+/// [`HashMap::insert_interned`]: struct.HashMap.html#method.insert_interned
+#[derive(Debug, Default, Clone)]
+pub struct Interner(HashSet<Arc<str>>);
+
+impl Interner {
+    /// Creates an empty `Interner`.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared string equal to `s`, adding it to the interner if
+    /// it wasn't interned yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::Interner;
+    /// use std::sync::Arc;
+    ///
+    /// let mut interner = Interner::new();
+    /// let a = interner.intern("key");
+    /// let b = interner.intern("key");
+    /// assert!(Arc::ptr_eq(&a, &b));
+    /// ```
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(interned) = self.0.get(s) {
+            interned.clone()
+        } else {
+            let interned: Arc<str> = Arc::from(s);
+            self.0.insert(interned.clone());
+            interned
+        }
+    }
+
+    /// Returns the number of interned strings.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if no strings were interned.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<V, S> HashMap<Arc<str>, V, S>
+where
+    S: BuildHasher + Default,
+{
+    /// Inserts a key-value pair into the map, like [`insert`] does, with
+    /// the key taken from `interner`.
+    ///
+    /// If the map already had the key its value is updated and the stored
+    /// key is kept, the interner is only consulted for new keys.
+    ///
+    /// [`insert`]: #method.insert
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::{HashMap, Interner};
+    /// use std::sync::Arc;
+    ///
+    /// let mut interner = Interner::new();
+    /// let mut a = HashMap::new();
+    /// let mut b = HashMap::new();
+    /// a.insert_interned(&mut interner, "name", 1);
+    /// b.insert_interned(&mut interner, "name", 2);
+    ///
+    /// let ka = a.keys().next().unwrap();
+    /// let kb = b.keys().next().unwrap();
+    /// assert!(Arc::ptr_eq(ka, kb));
+    /// assert_eq!(interner.len(), 1);
+    /// ```
+    pub fn insert_interned(&mut self, interner: &mut Interner, key: &str, value: V) -> Option<V> {
+        if let Some(old) = self.get_mut(key) {
+            return Some(std::mem::replace(old, value));
+        }
+        self.insert(interner.intern(key), value)
+    }
+}
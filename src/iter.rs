@@ -3,6 +3,12 @@ use core::hash::{BuildHasher, Hash};
 use std::iter::{FromIterator, IntoIterator};
 
 /// Iterator over the key value pairs of a Halfbrown map
+///
+/// Iterating from the back is cheap for vector backed maps, but allocates
+/// a buffer for the pairs that are left on map backed maps, see the
+/// `DoubleEndedIterator` implementation. The order of the vector backend is
+/// an implementation detail that changes with removals and promotion either
+/// way.
 pub struct Iter<'a, K, V>(IterInt<'a, K, V>);
 
 impl<'a, K, V> From<IterInt<'a, K, V>> for Iter<'a, K, V> {
@@ -13,6 +19,8 @@ impl<'a, K, V> From<IterInt<'a, K, V>> for Iter<'a, K, V> {
 pub(crate) enum IterInt<'a, K, V> {
     Map(hashbrown::hash_map::Iter<'a, K, V>),
     Vec(std::slice::Iter<'a, (K, V)>),
+    /// the rest of a map backed iterator after `next_back` was called
+    Buffered(std::vec::IntoIter<(&'a K, &'a V)>),
 }
 
 impl<'a, K, V> Iterator for Iter<'a, K, V> {
@@ -28,6 +36,7 @@ impl<'a, K, V> Iterator for Iter<'a, K, V> {
                     None
                 }
             }
+            IterInt::Buffered(m) => m.next(),
         }
     }
     #[inline]
@@ -35,11 +44,36 @@ impl<'a, K, V> Iterator for Iter<'a, K, V> {
         match &self.0 {
             IterInt::Map(m) => m.size_hint(),
             IterInt::Vec(m) => m.size_hint(),
+            IterInt::Buffered(m) => m.size_hint(),
+        }
+    }
+}
+
+/// **Allocates on map backed maps:** `hashbrown`'s iterators only walk
+/// forward, so the first `next_back` on an iterator over a map backed map
+/// collects the pairs that are left into a `Vec`, which is O(n) in time and
+/// memory. Later calls, in either direction, are served from that buffer.
+/// Iterators over vector backed maps walk the vector from the back without
+/// allocating.
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if let IterInt::Map(m) = &mut self.0 {
+            self.0 = IterInt::Buffered(m.collect::<Vec<_>>().into_iter());
+        }
+        match &mut self.0 {
+            IterInt::Vec(m) => m.next_back().map(|(k, v)| (k, v)),
+            IterInt::Buffered(m) => m.next_back(),
+            IterInt::Map(_) => unreachable!(),
         }
     }
 }
 
 /// Into iterator for a Halfbrown map
+///
+/// Pairs are moved out as they are yielded, the vector or table backing
+/// them is kept until the iterator is dropped, which also drops the pairs
+/// that weren't yielded.
 pub struct IntoIter<K, V>(IntoIterInt<K, V>);
 enum IntoIterInt<K, V> {
     Map(hashbrown::hash_map::IntoIter<K, V>),
@@ -79,10 +113,74 @@ impl<K, V> Iterator for IntoIter<K, V> {
     }
 }
 
-impl<K, V, S> IntoIterator for HashMap<K, V, S>
-where
-    K: Eq + Hash,
-{
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {
+    #[inline]
+    fn len(&self) -> usize {
+        IntoIter::len(self)
+    }
+}
+
+/// Consuming iterator over the keys of a Halfbrown map
+pub struct IntoKeys<K, V> {
+    inner: IntoIter<K, V>,
+}
+
+impl<K, V> IntoKeys<K, V> {
+    pub(crate) fn new(inner: IntoIter<K, V>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<K, V> Iterator for IntoKeys<K, V> {
+    type Item = K;
+    #[inline]
+    fn next(&mut self) -> Option<K> {
+        self.inner.next().map(|(k, _)| k)
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoKeys<K, V> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+/// Consuming iterator over the values of a Halfbrown map
+pub struct IntoValues<K, V> {
+    inner: IntoIter<K, V>,
+}
+
+impl<K, V> IntoValues<K, V> {
+    pub(crate) fn new(inner: IntoIter<K, V>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<K, V> Iterator for IntoValues<K, V> {
+    type Item = V;
+    #[inline]
+    fn next(&mut self) -> Option<V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoValues<K, V> {
+    #[inline]
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<K, V, S> IntoIterator for HashMap<K, V, S> {
     type Item = (K, V);
     type IntoIter = IntoIter<K, V>;
 
@@ -91,15 +189,12 @@ where
         match self.0 {
             HashMapInt::Map(m) => IntoIter(IntoIterInt::Map(m.into_iter())),
             HashMapInt::Vec(m) => IntoIter(IntoIterInt::Vec(m.into_iter())),
-            HashMapInt::None => unreachable!(),
+            HashMapInt::None => none_state!(),
         }
     }
 }
 
-impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S>
-where
-    K: Eq + Hash,
-{
+impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S> {
     type Item = (&'a K, &'a V);
     type IntoIter = Iter<'a, K, V>;
 
@@ -109,15 +204,28 @@ where
     }
 }
 
+impl<'a, K, V, S> IntoIterator for &'a mut HashMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    #[inline]
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
 impl<K, V, S> FromIterator<(K, V)> for HashMap<K, V, S>
 where
     K: Eq + Hash,
     S: BuildHasher + Default,
 {
+    /// Maps that are known to outgrow the vector from the iterator's lower
+    /// size bound start out map backed, everything else starts as a vector.
     #[inline]
     fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
         let iter = iter.into_iter();
-        let mut map = Self::with_capacity_and_hasher(iter.size_hint().0, S::default());
+        let lower = iter.size_hint().0;
+        let mut map = Self::presized(lower, S::default());
         iter.for_each(|(k, v)| {
             map.insert(k, v);
         });
@@ -125,6 +233,45 @@ where
     }
 }
 
+impl<K, V, S> Extend<(K, V)> for HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    /// An empty map is sized for the lower size bound of the iterator up
+    /// front. A map that already holds keys may only get updates, so it's
+    /// promoted once it actually outgrows the vector, and then sized for
+    /// the rest of the iterator.
+    #[inline]
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        let mut iter = iter.into_iter();
+        self.grow_for_batch(iter.size_hint().0);
+        while let Some((k, v)) = iter.next() {
+            match &mut self.0 {
+                HashMapInt::Vec(m) if m.len() >= self.1.vec_limit() => {
+                    self.swap_to_map(iter.size_hint().0.saturating_add(1));
+                    self.insert_nocheck(k, v);
+                }
+                _ => {
+                    self.insert(k, v);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K, V, S> Extend<(&'a K, &'a V)> for HashMap<K, V, S>
+where
+    K: Eq + Hash + Copy,
+    V: Copy,
+    S: BuildHasher + Default,
+{
+    #[inline]
+    fn extend<T: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: T) {
+        self.extend(iter.into_iter().map(|(&k, &v)| (k, v)));
+    }
+}
+
 /// Mutable iterator over the key value pairs
 pub struct IterMut<'a, K, V>(IterMutInt<'a, K, V>);
 
@@ -137,6 +284,8 @@ impl<'a, K, V> From<IterMutInt<'a, K, V>> for IterMut<'a, K, V> {
 pub(crate) enum IterMutInt<'a, K, V> {
     Map(hashbrown::hash_map::IterMut<'a, K, V>),
     Vec(std::slice::IterMut<'a, (K, V)>),
+    /// the rest of a map backed iterator after `next_back` was called
+    Buffered(std::vec::IntoIter<(&'a K, &'a mut V)>),
 }
 
 impl<'a, K, V> Iterator for IterMut<'a, K, V> {
@@ -147,6 +296,7 @@ impl<'a, K, V> Iterator for IterMut<'a, K, V> {
         match &mut self.0 {
             IterMutInt::Map(m) => m.next(),
             IterMutInt::Vec(m) => m.next().map(|(k, v)| (k as &K, v)),
+            IterMutInt::Buffered(m) => m.next(),
         }
     }
     #[inline]
@@ -154,6 +304,27 @@ impl<'a, K, V> Iterator for IterMut<'a, K, V> {
         match &self.0 {
             IterMutInt::Map(m) => m.size_hint(),
             IterMutInt::Vec(m) => m.size_hint(),
+            IterMutInt::Buffered(m) => m.size_hint(),
+        }
+    }
+}
+
+/// **Allocates on map backed maps:** like for [`Iter`], the first
+/// `next_back` on a map backed map collects the pairs that are left into a
+/// `Vec`, which is O(n) in time and memory. Vector backed maps don't
+/// allocate.
+///
+/// [`Iter`]: struct.Iter.html
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if let IterMutInt::Map(m) = &mut self.0 {
+            self.0 = IterMutInt::Buffered(m.collect::<Vec<_>>().into_iter());
+        }
+        match &mut self.0 {
+            IterMutInt::Vec(m) => m.next_back().map(|(k, v)| (k as &K, v)),
+            IterMutInt::Buffered(m) => m.next_back(),
+            IterMutInt::Map(_) => unreachable!(),
         }
     }
 }
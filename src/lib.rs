@@ -20,7 +20,7 @@
 
 #![warn(unused_extern_crates)]
 #![cfg_attr(
-    feature = "cargo-clippy",
+    clippy,
     deny(
         clippy::all,
         clippy::unwrap_used,
@@ -28,47 +28,178 @@
         clippy::pedantic
     ),
     // We might want to revisit inline_always
-    allow(clippy::module_name_repetitions, clippy::inline_always)
+    allow(
+        clippy::module_name_repetitions,
+        clippy::inline_always,
+        clippy::multiple_bound_locations
+    )
 )]
 #![deny(missing_docs)]
 
+/// Panics on the transient `None` state a map is only in while its backend
+/// is swapped, observing it means a transition was interrupted.
+macro_rules! none_state {
+    () => {
+        unreachable!("halfbrown internal invariant violated: map observed in transient None state")
+    };
+}
+
 mod entry;
+mod entry_ref;
+mod interner;
 mod iter;
+mod lookup;
 mod macros;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 mod raw_entry;
+#[cfg(feature = "rayon")]
+mod rayon;
 #[cfg(feature = "serde")]
 mod serde;
 mod vecmap;
 
 pub use crate::entry::*;
+pub use crate::entry_ref::*;
+pub use crate::interner::*;
 pub use crate::iter::*;
+pub use crate::lookup::*;
 pub use crate::raw_entry::*;
+#[cfg(feature = "rayon")]
+pub use crate::rayon::{ParIter, ParIterMut};
 use crate::vecmap::VecMap;
 use core::borrow::Borrow;
-use core::hash::{BuildHasher, Hash};
+use core::cmp::Ordering;
+use core::hash::{BuildHasher, Hash, Hasher};
 use hashbrown::{self, HashMap as HashBrown};
 use std::default::Default;
 use std::fmt::{self, Debug};
-use std::ops::Index;
+use std::iter::{Product, Sum};
+use std::ops::{AddAssign, ControlFlow, Index};
 
 #[cfg(feature = "fxhash")]
 pub use fxhash::FxBuildHasher as DefaultHashBuilder;
+/// Default hasher for `HashMap`, randomly seeded for every map.
 #[cfg(not(feature = "fxhash"))]
-pub use hashbrown::hash_map::DefaultHashBuilder;
+pub type DefaultHashBuilder = ahash::RandomState;
+pub use hashbrown::TryReserveError;
 
 /// Maximum nymber of elements before the representaiton is swapped from
-/// Vec to `HashMap`
+/// Vec to `HashMap`, unless a map is created with
+/// [`HashMap::with_max_vec_capacity`]
 pub const VEC_LIMIT_UPPER: usize = 32;
 
 /// `HashMap` implementation that alternates between a vector
 /// and a hashmap to improve performance for low key counts.
 #[derive(Clone)]
-pub struct HashMap<K, V, S = DefaultHashBuilder>(HashMapInt<K, V, S>);
+pub struct HashMap<K, V, S = DefaultHashBuilder>(HashMapInt<K, V, S>, Hints);
+
+/// Tuning hints and bookkeeping of a map, they never change its content.
+///
+/// Every map carries these, so the flags are packed into a byte and the
+/// knobs that are rarely turned only get allocated once they are.
+#[derive(Clone, Debug, Default)]
+struct Hints {
+    knobs: Option<Box<Knobs>>,
+    flags: u8,
+}
+
+/// Hints that differ from the defaults for few maps only.
+#[derive(Clone, Debug)]
+struct Knobs {
+    /// room for additional elements to reserve when promoting to a map
+    headroom: usize,
+    /// number of elements the vector backend holds before it's promoted
+    vec_limit: usize,
+}
+
+impl Default for Knobs {
+    fn default() -> Self {
+        Self {
+            headroom: 0,
+            vec_limit: VEC_LIMIT_UPPER,
+        }
+    }
+}
+
+impl Hints {
+    /// `insert_nocheck` was used on the vector backend, so it may hold
+    /// duplicate keys
+    const MAYBE_DUPLICATES: u8 = 1;
+    /// the map was hinted to be large, it never moves back to a vector
+    const LARGE: u8 = 1 << 1;
+
+    #[inline]
+    fn knobs_mut(&mut self) -> &mut Knobs {
+        self.knobs.get_or_insert_with(Box::default)
+    }
+
+    #[inline]
+    fn headroom(&self) -> usize {
+        self.knobs.as_ref().map_or(0, |k| k.headroom)
+    }
+
+    #[inline]
+    fn set_headroom(&mut self, headroom: usize) {
+        if self.knobs.is_some() || headroom != 0 {
+            self.knobs_mut().headroom = headroom;
+        }
+    }
+
+    #[inline]
+    fn vec_limit(&self) -> usize {
+        self.knobs.as_ref().map_or(VEC_LIMIT_UPPER, |k| k.vec_limit)
+    }
+
+    #[inline]
+    fn set_vec_limit(&mut self, vec_limit: usize) {
+        if self.knobs.is_some() || vec_limit != VEC_LIMIT_UPPER {
+            self.knobs_mut().vec_limit = vec_limit;
+        }
+    }
+
+    #[inline]
+    fn flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    #[inline]
+    fn set_flag(&mut self, flag: u8, on: bool) {
+        if on {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    #[inline]
+    fn large(&self) -> bool {
+        self.flag(Self::LARGE)
+    }
+
+    /// Marks the map as large, once marked it stays large.
+    #[inline]
+    fn mark_large(&mut self, large: bool) {
+        if large {
+            self.set_flag(Self::LARGE, true);
+        }
+    }
+
+    #[inline]
+    fn maybe_duplicates(&self) -> bool {
+        self.flag(Self::MAYBE_DUPLICATES)
+    }
+
+    #[inline]
+    fn set_maybe_duplicates(&mut self, maybe_duplicates: bool) {
+        self.set_flag(Self::MAYBE_DUPLICATES, maybe_duplicates);
+    }
+}
 
 impl<K: Default, V: Default> Default for HashMap<K, V, DefaultHashBuilder> {
     #[inline]
     fn default() -> Self {
-        Self(HashMapInt::default())
+        Self::from_backend(HashMapInt::default())
     }
 }
 
@@ -115,13 +246,20 @@ impl<K, V> HashMap<K, V, DefaultHashBuilder> {
     #[inline]
     #[must_use]
     pub fn new() -> Self {
-        Self(HashMapInt::Vec(VecMap::new()))
+        Self::from_backend(HashMapInt::Vec(VecMap::new()))
     }
     /// Creates an empty `HashMap` with the specified capacity.
     ///
     /// The hash map will be able to hold at least `capacity` elements without
     /// reallocating. If `capacity` is 0, the hash map will not allocate.
     ///
+    /// # Panics
+    ///
+    /// Panics if the allocation size overflows, use [`try_with_capacity`]
+    /// to handle that.
+    ///
+    /// [`try_with_capacity`]: #method.try_with_capacity
+    ///
     /// # Examples
     ///
     /// ```
@@ -131,38 +269,201 @@ impl<K, V> HashMap<K, V, DefaultHashBuilder> {
     #[inline]
     #[must_use]
     pub fn with_capacity(capacity: usize) -> Self {
-        Self(if capacity > VEC_LIMIT_UPPER {
-            HashMapInt::Map(HashBrown::with_capacity_and_hasher(
-                capacity,
-                DefaultHashBuilder::default(),
-            ))
+        Self::presized(capacity, DefaultHashBuilder::default()).sized_for(capacity)
+    }
+
+    /// Creates an empty `HashMap` with the specified capacity like
+    /// [`with_capacity`] does, but returns an error instead of panicking or
+    /// aborting when the memory can't be allocated.
+    ///
+    /// [`with_capacity`]: #method.with_capacity
+    ///
+    /// # Errors
+    ///
+    /// If the capacity overflows, or the allocator reports a failure, then an error
+    /// is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// let map: HashMap<&str, i32> = HashMap::try_with_capacity(10).unwrap();
+    /// assert!(map.is_vec());
+    /// assert!(HashMap::<&str, i32>::try_with_capacity(usize::MAX).is_err());
+    /// ```
+    #[inline]
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, TryReserveError>
+    where
+        K: Eq + Hash,
+    {
+        Ok(Self::from_backend(if capacity > VEC_LIMIT_UPPER {
+            let mut m = HashBrown::with_hasher(DefaultHashBuilder::default());
+            m.try_reserve(capacity)?;
+            HashMapInt::Map(m)
         } else {
-            HashMapInt::Vec(VecMap::with_capacity(capacity))
+            let mut m = VecMap::new();
+            m.try_reserve(capacity)?;
+            HashMapInt::Vec(m)
         })
+        .sized_for(capacity))
+    }
+    /// Creates an empty `HashMap` that starts out with room for `floor`
+    /// entries in its vector backend, so the first inserts never have to
+    /// reallocate.
+    ///
+    /// As the vector backend never holds more than [`VEC_LIMIT_UPPER`]
+    /// entries the floor is capped at that. Unlike [`new`] this allocates
+    /// right away unless `floor` is 0.
+    ///
+    /// [`VEC_LIMIT_UPPER`]: constant.VEC_LIMIT_UPPER.html
+    /// [`new`]: #method.new
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// let mut map: HashMap<&str, i32> = HashMap::with_min_vec_capacity(8);
+    /// assert!(map.is_vec());
+    /// assert!(map.capacity() >= 8);
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_min_vec_capacity(floor: usize) -> Self {
+        Self::from_backend(HashMapInt::Vec(VecMap::with_capacity(
+            floor.min(VEC_LIMIT_UPPER),
+        )))
+    }
+
+    /// Creates an empty vector backed `HashMap` that is promoted to the map
+    /// backend once it holds more than `limit` elements, instead of the
+    /// default [`VEC_LIMIT_UPPER`]. The limit is kept by clones of the map.
+    ///
+    /// [`VEC_LIMIT_UPPER`]: constant.VEC_LIMIT_UPPER.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// let mut map: HashMap<u8, u8> = HashMap::with_max_vec_capacity(8);
+    /// assert_eq!(map.max_vec_capacity(), 8);
+    /// for i in 0..8 {
+    ///     map.insert(i, i);
+    /// }
+    /// assert!(map.is_vec());
+    /// map.insert(8, 8);
+    /// assert!(map.is_map());
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_max_vec_capacity(limit: usize) -> Self {
+        let mut map = Self::new();
+        map.1.set_vec_limit(limit);
+        map
     }
+
     /// Same as with capacity with the difference that it, despite of the
     /// requested size always returns a vector. This allows quicker generation
     /// when used in combination with `insert_nocheck`.
     ///
+    /// The vector limit still applies: inserting a new key into a map that
+    /// holds [`VEC_LIMIT_UPPER`] elements promotes it to the map backend,
+    /// which is sized for at least `capacity` elements. Use
+    /// [`fixed_vec_with_capacity`] for a map that never leaves the vector.
+    ///
+    /// [`VEC_LIMIT_UPPER`]: constant.VEC_LIMIT_UPPER.html
+    /// [`fixed_vec_with_capacity`]: #method.fixed_vec_with_capacity
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocation size overflows.
+    ///
     /// # Examples
     ///
     /// ```
-    /// use halfbrown::HashMap;
-    /// let mut map: HashMap<&str, i32> = HashMap::vec_with_capacity(128);
+    /// use halfbrown::{HashMap, VEC_LIMIT_UPPER};
+    /// let mut map: HashMap<usize, i32> = HashMap::vec_with_capacity(128);
     /// assert!(map.is_vec());
+    /// for i in 0..=VEC_LIMIT_UPPER {
+    ///     map.insert(i, 0);
+    /// }
+    /// assert!(map.is_map());
+    /// assert!(map.capacity() >= 128);
     /// ```
     #[inline]
     #[must_use]
     pub fn vec_with_capacity(capacity: usize) -> Self {
-        Self(HashMapInt::Vec(VecMap::with_capacity(capacity)))
+        Self::from_backend(HashMapInt::Vec(VecMap::with_capacity(capacity)))
+    }
+
+    /// Creates an empty vector backed `HashMap` with room for `capacity`
+    /// elements that is never promoted to the map backend, no matter how
+    /// many elements it holds.
+    ///
+    /// Lookups on a vector are linear, this is meant for maps with few
+    /// lookups or cheap key comparisons where the size is known up front.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocation size overflows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// let mut map: HashMap<usize, i32> = HashMap::fixed_vec_with_capacity(128);
+    /// for i in 0..128 {
+    ///     map.insert(i, 0);
+    /// }
+    /// assert!(map.is_vec());
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn fixed_vec_with_capacity(capacity: usize) -> Self {
+        let mut map = Self::vec_with_capacity(capacity);
+        map.1.set_vec_limit(usize::MAX);
+        map
     }
 }
 
 impl<K, V, S> HashMap<K, V, S> {
+    #[inline]
+    fn from_backend(backend: HashMapInt<K, V, S>) -> Self {
+        Self(backend, Hints::default())
+    }
+
+    /// Creates an empty map with room for `capacity` elements, vector backed
+    /// if they fit into a vector. Unlike [`with_capacity`] this doesn't count
+    /// as a hint that the map is going to be large.
+    ///
+    /// [`with_capacity`]: #method.with_capacity
+    #[inline]
+    pub(crate) fn presized(capacity: usize, hash_builder: S) -> Self {
+        Self::from_backend(if capacity > VEC_LIMIT_UPPER {
+            HashMapInt::Map(HashBrown::with_capacity_and_hasher(capacity, hash_builder))
+        } else {
+            HashMapInt::Vec(VecMap::with_capacity_and_hasher(capacity, hash_builder))
+        })
+    }
+
+    /// Marks the map as large if a caller asked for more room than fits
+    /// into its vector, like [`hint_large`] without changing the backend.
+    ///
+    /// [`hint_large`]: #method.hint_large
+    #[inline]
+    fn sized_for(mut self, capacity: usize) -> Self {
+        self.1.mark_large(capacity > self.1.vec_limit());
+        self
+    }
+
     /// Creates an empty `HashMap` which will use the given hash builder to hash
     /// keys.
     ///
-    /// The created map has the default initial capacity.
+    /// The created map has the default initial capacity. Unlike [`new`] the
+    /// map starts out map backed, use [`vec_with_hasher`] for a vector backed
+    /// map with a custom hasher.
+    ///
+    /// [`new`]: #method.new
+    /// [`vec_with_hasher`]: #method.vec_with_hasher
     ///
     /// Warning: `hash_builder` is normally randomly generated, and
     /// is designed to allow `HashMaps` to be resistant to attacks that
@@ -181,14 +482,42 @@ impl<K, V, S> HashMap<K, V, S> {
     /// ```
     #[inline]
     pub fn with_hasher(hash_builder: S) -> Self {
-        Self(HashMapInt::Map(HashBrown::with_hasher(hash_builder)))
+        Self::from_backend(HashMapInt::Map(HashBrown::with_hasher(hash_builder)))
+    }
+
+    /// Creates an empty vector backed `HashMap` which will use the given
+    /// hash builder to hash keys once it gets promoted to a map.
+    ///
+    /// This is the counterpart of [`new`] for custom hashers, like it the map
+    /// does not allocate until it is first inserted into.
+    ///
+    /// [`new`]: #method.new
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// use hashbrown::hash_map::DefaultHashBuilder;
+    ///
+    /// let s = DefaultHashBuilder::default();
+    /// let mut map = HashMap::vec_with_hasher(s);
+    /// map.insert(1, 2);
+    /// assert!(map.is_vec());
+    /// ```
+    #[inline]
+    pub fn vec_with_hasher(hash_builder: S) -> Self {
+        Self::from_backend(HashMapInt::Vec(VecMap::with_capacity_and_hasher(
+            0,
+            hash_builder,
+        )))
     }
 
     /// Creates an empty `HashMap` with the specified capacity, using `hash_builder`
     /// to hash the keys.
     ///
     /// The hash map will be able to hold at least `capacity` elements without
-    /// reallocating. If `capacity` is 0, the hash map will not allocate.
+    /// reallocating. If `capacity` is 0, the hash map will not allocate. The
+    /// map starts out map backed regardless of `capacity`.
     ///
     /// Warning: `hash_builder` is normally randomly generated, and
     /// is designed to allow `HashMaps` to be resistant to attacks that
@@ -207,10 +536,11 @@ impl<K, V, S> HashMap<K, V, S> {
     /// ```
     #[inline]
     pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
-        Self(HashMapInt::Map(HashBrown::with_capacity_and_hasher(
+        Self::from_backend(HashMapInt::Map(HashBrown::with_capacity_and_hasher(
             capacity,
             hash_builder,
         )))
+        .sized_for(capacity)
     }
 
     /// Returns a reference to the map's [`BuildHasher`].
@@ -231,7 +561,7 @@ impl<K, V, S> HashMap<K, V, S> {
         match &self.0 {
             HashMapInt::Map(m) => m.hasher(),
             HashMapInt::Vec(m) => m.hasher(),
-            HashMapInt::None => unreachable!(),
+            HashMapInt::None => none_state!(),
         }
     }
 
@@ -248,15 +578,65 @@ impl<K, V, S> HashMap<K, V, S> {
     /// assert!(map.capacity() >= 100);
     /// ```
     #[inline]
-    #[allow(clippy::missing_panics_doc)]
     pub fn capacity(&self) -> usize {
         match &self.0 {
             HashMapInt::Map(m) => m.capacity(),
             HashMapInt::Vec(m) => m.capacity(),
-            HashMapInt::None => unimplemented!(),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Returns the number of elements the map can hold before it either
+    /// has to reallocate or switch backends.
+    ///
+    /// For the map backend this is the same as [`capacity`], which already
+    /// accounts for the load factor. The vector backend on the other hand
+    /// switches to a map once it holds [`VEC_LIMIT_UPPER`] elements, no
+    /// matter how much room its allocation has left, which is what sets
+    /// this apart from [`capacity`].
+    ///
+    /// [`capacity`]: #method.capacity
+    /// [`VEC_LIMIT_UPPER`]: constant.VEC_LIMIT_UPPER.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::{HashMap, VEC_LIMIT_UPPER};
+    /// let map: HashMap<i32, i32> = HashMap::vec_with_capacity(100);
+    /// assert!(map.capacity() >= 100);
+    /// assert_eq!(map.effective_capacity(), VEC_LIMIT_UPPER);
+    /// ```
+    #[inline]
+    pub fn effective_capacity(&self) -> usize {
+        match &self.0 {
+            HashMapInt::Map(m) => m.capacity(),
+            HashMapInt::Vec(m) => m.capacity().min(self.1.vec_limit()),
+            HashMapInt::None => none_state!(),
         }
     }
 
+    /// Returns the number of elements a vector backed map holds before it
+    /// is promoted to the map backend.
+    ///
+    /// This is [`VEC_LIMIT_UPPER`] unless the map was created with
+    /// [`with_max_vec_capacity`].
+    ///
+    /// [`VEC_LIMIT_UPPER`]: constant.VEC_LIMIT_UPPER.html
+    /// [`with_max_vec_capacity`]: #method.with_max_vec_capacity
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::{HashMap, VEC_LIMIT_UPPER};
+    /// let map: HashMap<i32, i32> = HashMap::new();
+    /// assert_eq!(map.max_vec_capacity(), VEC_LIMIT_UPPER);
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn max_vec_capacity(&self) -> usize {
+        self.1.vec_limit()
+    }
+
     /// An iterator visiting all keys in arbitrary order.
     /// The iterator element type is `&'a K`.
     ///
@@ -327,34 +707,40 @@ impl<K, V, S> HashMap<K, V, S> {
         }
     }
 
-    /// An iterator visiting all key-value pairs in arbitrary order.
-    /// The iterator element type is `(&'a K, &'a V)`.
+    /// Calls `f` with every key and a mutable reference to its value in
+    /// arbitrary order, stopping at the first `Break` `f` returns and
+    /// returning it.
     ///
     /// # Examples
     ///
     /// ```
     /// use halfbrown::HashMap;
+    /// use std::ops::ControlFlow;
     ///
-    /// let mut map = HashMap::new();
+    /// let mut map: HashMap<&str, u32> = HashMap::new();
     /// map.insert("a", 1);
     /// map.insert("b", 2);
-    /// map.insert("c", 3);
     ///
-    /// for (key, val) in map.iter() {
-    ///     println!("key: {} val: {}", key, val);
-    /// }
+    /// let r = map.try_for_each_value(|_, v| {
+    ///     *v += 10;
+    ///     ControlFlow::<()>::Continue(())
+    /// });
+    /// assert_eq!(r, ControlFlow::Continue(()));
+    /// assert_eq!(map["b"], 12);
+    ///
+    /// let r = map.try_for_each_value(|k, v| if *v > 10 { ControlFlow::Break(*k) } else { ControlFlow::Continue(()) });
+    /// assert!(r.is_break());
     /// ```
-    pub fn iter(&self) -> Iter<'_, K, V> {
-        match &self.0 {
-            HashMapInt::Map(m) => IterInt::Map(m.iter()).into(),
-            HashMapInt::Vec(m) => IterInt::Vec(m.iter()).into(),
-            HashMapInt::None => unreachable!(),
-        }
+    pub fn try_for_each_value<B, F>(&mut self, mut f: F) -> ControlFlow<B>
+    where
+        F: FnMut(&K, &mut V) -> ControlFlow<B>,
+    {
+        self.iter_mut().try_for_each(|(k, v)| f(k, v))
     }
 
-    /// An iterator visiting all key-value pairs in arbitrary order,
-    /// with mutable references to the values.
-    /// The iterator element type is `(&'a K, &'a mut V)`.
+    /// Creates a consuming iterator visiting all the keys in arbitrary order.
+    /// The map cannot be used after calling this.
+    /// The iterator element type is `K`.
     ///
     /// # Examples
     ///
@@ -366,232 +752,220 @@ impl<K, V, S> HashMap<K, V, S> {
     /// map.insert("b", 2);
     /// map.insert("c", 3);
     ///
-    /// // Update all values
-    /// for (_, val) in map.iter_mut() {
-    ///     *val *= 2;
-    /// }
-    ///
-    /// for (key, val) in &map {
-    ///     println!("key: {} val: {}", key, val);
-    /// }
+    /// let mut keys: Vec<&str> = map.into_keys().collect();
+    /// keys.sort_unstable();
+    /// assert_eq!(keys, ["a", "b", "c"]);
     /// ```
-    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
-        match &mut self.0 {
-            HashMapInt::Map(m) => IterMutInt::Map(m.iter_mut()).into(),
-            HashMapInt::Vec(m) => IterMutInt::Vec(m.iter_mut()).into(),
-            HashMapInt::None => unreachable!(),
-        }
+    #[inline]
+    pub fn into_keys(self) -> IntoKeys<K, V> {
+        IntoKeys::new(self.into_iter())
     }
 
-    /// Returns the number of elements in the map.
+    /// Creates a consuming iterator visiting all the values in arbitrary
+    /// order. The map cannot be used after calling this.
+    /// The iterator element type is `V`.
     ///
     /// # Examples
     ///
     /// ```
     /// use halfbrown::HashMap;
     ///
-    /// let mut a = HashMap::new();
-    /// assert_eq!(a.len(), 0);
-    /// a.insert(1, "a");
-    /// assert_eq!(a.len(), 1);
+    /// let mut map = HashMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    ///
+    /// let mut values: Vec<i32> = map.into_values().collect();
+    /// values.sort_unstable();
+    /// assert_eq!(values, [1, 2, 3]);
     /// ```
     #[inline]
-    pub fn len(&self) -> usize {
-        match &self.0 {
-            HashMapInt::Map(m) => m.len(),
-            HashMapInt::Vec(m) => m.len(),
-            HashMapInt::None => unreachable!(),
-        }
+    pub fn into_values(self) -> IntoValues<K, V> {
+        IntoValues::new(self.into_iter())
     }
 
-    /// Returns `true` if the map contains no elements.
+    /// An iterator visiting all key-value pairs in arbitrary order.
+    /// The iterator element type is `(&'a K, &'a V)`.
     ///
     /// # Examples
     ///
     /// ```
     /// use halfbrown::HashMap;
     ///
-    /// let mut a = HashMap::new();
-    /// assert!(a.is_empty());
-    /// a.insert(1, "a");
-    /// assert!(!a.is_empty());
+    /// let mut map = HashMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    ///
+    /// for (key, val) in map.iter() {
+    ///     println!("key: {} val: {}", key, val);
+    /// }
     /// ```
-    #[inline]
-    pub fn is_empty(&self) -> bool {
-        match &self.0 {
-            HashMapInt::Map(m) => m.is_empty(),
-            HashMapInt::Vec(m) => m.is_empty(),
-            HashMapInt::None => unreachable!(),
-        }
-    }
-
-    /// Clears the map, returning all key-value pairs as an iterator. Keeps the
-    /// allocated memory for reuse.
     ///
-    /// # Examples
+    /// # Reference stability
     ///
-    /// ```
+    /// The references handed out by `iter`, `get` and friends stay valid for
+    /// as long as the map is only borrowed immutably, so they can be
+    /// collected into an index. Anything that may move entries, an insert
+    /// that grows the vector or the table, a promotion, or a removal, needs
+    /// `&mut self` and the borrow checker rejects it while the references
+    /// are alive:
+    ///
+    /// ```compile_fail,E0502
     /// use halfbrown::HashMap;
     ///
-    /// let mut a = HashMap::new();
-    /// a.insert(1, "a");
-    /// a.insert(2, "b");
+    /// let mut map = HashMap::new();
+    /// map.insert(1, 1);
+    /// let index: Vec<(&i32, &i32)> = map.iter().collect();
+    /// map.insert(2, 2);
+    /// assert_eq!(index.len(), 1);
+    /// ```
     ///
-    /// for (k, v) in a.drain().take(1) {
-    ///     assert!(k == 1 || k == 2);
-    ///     assert!(v == "a" || v == "b");
-    /// }
+    /// This includes the insert that promotes a full vector to the map
+    /// backend:
     ///
-    /// assert!(a.is_empty());
+    /// ```compile_fail,E0502
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<usize, usize> = (0..32).map(|i| (i, i)).collect();
+    /// let v = map.get(&0).expect("present");
+    /// map.insert(32, 32);
+    /// assert_eq!(*v, 0);
     /// ```
-    #[inline]
-    pub fn drain(&mut self) -> Drain<K, V> {
-        match &mut self.0 {
-            HashMapInt::Map(m) => Drain(DrainInt::Map(m.drain())),
-            HashMapInt::Vec(m) => Drain(DrainInt::Vec(m.drain())),
-            HashMapInt::None => unreachable!(),
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        match &self.0 {
+            HashMapInt::Map(m) => IterInt::Map(m.iter()).into(),
+            HashMapInt::Vec(m) => IterInt::Vec(m.iter()).into(),
+            HashMapInt::None => none_state!(),
         }
     }
 
-    /// Clears the map, removing all key-value pairs. Keeps the allocated memory
-    /// for reuse.
+    /// An iterator visiting all key-value pairs in arbitrary order,
+    /// yielding copies of the keys and values.
     ///
     /// # Examples
     ///
     /// ```
     /// use halfbrown::HashMap;
     ///
-    /// let mut a = HashMap::new();
-    /// a.insert(1, "a");
-    /// a.clear();
-    /// assert!(a.is_empty());
+    /// let mut map = HashMap::new();
+    /// map.insert(1, 10);
+    /// map.insert(2, 20);
+    ///
+    /// let sum: u32 = map.iter_copied().map(|(k, v)| k * v).sum();
+    /// assert_eq!(sum, 50);
     /// ```
-    #[inline]
-    pub fn clear(&mut self) {
-        match &mut self.0 {
-            HashMapInt::Map(m) => m.clear(),
-            HashMapInt::Vec(m) => m.clear(),
-            HashMapInt::None => unreachable!(),
-        }
+    pub fn iter_copied(&self) -> impl Iterator<Item = (K, V)> + '_
+    where
+        K: Copy,
+        V: Copy,
+    {
+        self.iter().map(|(k, v)| (*k, *v))
     }
-}
 
-impl<K, V, S> HashMap<K, V, S>
-where
-    K: Eq + Hash,
-    S: BuildHasher,
-{
-    /// Reserves capacity for at least `additional` more elements to be inserted
-    /// in the `HashMap`. The collection may reserve more space to avoid
-    /// frequent reallocations.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the new allocation size overflows [`usize`].
+    /// An iterator visiting all key-value pairs whose key starts with
+    /// `prefix`, in arbitrary order.
     ///
-    /// [`usize`]: ../../std/primitive.usize.html
+    /// Both backends scan all entries to find the matching ones, so this is
+    /// linear in the size of the map.
     ///
     /// # Examples
     ///
     /// ```
     /// use halfbrown::HashMap;
-    /// let mut map: HashMap<&str, i32> = HashMap::new();
-    /// map.reserve(10);
-    /// ```
-    #[inline]
-    pub fn reserve(&mut self, additional: usize) {
-        match &mut self.0 {
-            HashMapInt::Map(m) => m.reserve(additional),
-            HashMapInt::Vec(m) => m.reserve(additional),
-            HashMapInt::None => unreachable!(),
-        }
-    }
-    /*
-    /// Tries to reserve capacity for at least `additional` more elements to be inserted
-    /// in the given `HashMap<K,V>`. The collection may reserve more space to avoid
-    /// frequent reallocations.
-    ///
-    /// # Errors
     ///
-    /// If the capacity overflows, or the allocator reports a failure, then an error
-    /// is returned.
-    ///
-    /// # Examples
+    /// let mut map = HashMap::new();
+    /// map.insert("apple", 1);
+    /// map.insert("apricot", 2);
+    /// map.insert("banana", 3);
     ///
+    /// let mut found: Vec<_> = map.keys_with_prefix("ap").map(|(k, _)| *k).collect();
+    /// found.sort_unstable();
+    /// assert_eq!(found, ["apple", "apricot"]);
     /// ```
-    /// #![feature(try_reserve)]
-    /// use halfbrown::HashMap;
-    /// let mut map: HashMap<&str, isize> = HashMap::new();
-    /// map.try_reserve(10).expect("why is the test harness OOMing on 10 bytes?");
-    /// ```
-    #[inline]
-    pub fn try_reserve(&mut self, additional: usize) -> Result<(), CollectionAllocErr> {
-        match &mut self.0 {
-            HashMapInt::Map(m) => m.try_reserve(additional),
-            HashMapInt::Vec(m) => m.try_reserve(additional),
-            HashMapInt::None => unreachable!(),
-        }
+    pub fn keys_with_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = (&'a K, &'a V)> + 'a
+    where
+        K: Borrow<str>,
+    {
+        // a sorted vector backend could find the range with a binary search
+        self.iter()
+            .filter(move |(k, _)| (*k).borrow().starts_with(prefix))
     }
-    */
-    /// Shrinks the capacity of the map as much as possible. It will drop
-    /// down as much as possible while maintaining the internal rules
-    /// and possibly leaving some space in accordance with the resize policy.
+
+    /// An iterator visiting all key-value pairs in arbitrary order,
+    /// with mutable references to the values.
+    /// The iterator element type is `(&'a K, &'a mut V)`.
     ///
     /// # Examples
     ///
     /// ```
     /// use halfbrown::HashMap;
     ///
-    /// let mut map: HashMap<i32, i32> = HashMap::with_capacity(100);
-    /// map.insert(1, 2);
-    /// map.insert(3, 4);
-    /// assert!(map.capacity() >= 100);
-    /// map.shrink_to_fit();
-    /// assert!(map.capacity() >= 2);
+    /// let mut map = HashMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    ///
+    /// // Update all values
+    /// for (_, val) in map.iter_mut() {
+    ///     *val *= 2;
+    /// }
+    ///
+    /// for (key, val) in &map {
+    ///     println!("key: {} val: {}", key, val);
+    /// }
     /// ```
-    pub fn shrink_to_fit(&mut self) {
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
         match &mut self.0 {
-            HashMapInt::Map(m) => m.shrink_to_fit(),
-            HashMapInt::Vec(m) => m.shrink_to_fit(),
-            HashMapInt::None => unreachable!(),
+            HashMapInt::Map(m) => IterMutInt::Map(m.iter_mut()).into(),
+            HashMapInt::Vec(m) => IterMutInt::Vec(m.iter_mut()).into(),
+            HashMapInt::None => none_state!(),
         }
     }
 
-    /// Gets the given key's corresponding entry in the map for in-place manipulation.
+    /// Replaces every value in the map with the result of calling `f` with
+    /// its key and the value, taken by move.
+    ///
+    /// This is useful when a value is easier to transform when owned, as
+    /// opposed to `values_mut` there is no need to swap a placeholder value
+    /// in by hand.
+    ///
+    /// Each value is taken out of the map with [`std::mem::take`], so if `f`
+    /// panics the value it was called with is left as `V::default()`.
+    ///
+    /// [`std::mem::take`]: https://doc.rust-lang.org/std/mem/fn.take.html
     ///
     /// # Examples
     ///
     /// ```
     /// use halfbrown::HashMap;
     ///
-    /// let mut letters = HashMap::new();
+    /// let mut map = HashMap::new();
+    /// map.insert("a", vec![1]);
+    /// map.insert("b", vec![2]);
     ///
-    /// for ch in "a short treatise on fungi".chars() {
-    ///     let counter = letters.entry(ch).or_insert(0);
-    ///     *counter += 1;
-    /// }
+    /// map.map_values_in_place(|k, mut v| {
+    ///     v.push(k.len());
+    ///     v
+    /// });
     ///
-    /// assert_eq!(letters[&'s'], 2);
-    /// assert_eq!(letters[&'t'], 3);
-    /// assert_eq!(letters[&'u'], 1);
-    /// assert_eq!(letters.get(&'y'), None);
+    /// assert_eq!(map["a"], vec![1, 1]);
+    /// assert_eq!(map["b"], vec![2, 1]);
     /// ```
-    pub fn entry(&mut self, key: K) -> Entry<K, V, S> {
-        match &mut self.0 {
-            HashMapInt::Map(m) => m.entry(key).into(),
-            HashMapInt::Vec(m) => m.entry(key).into(),
-            HashMapInt::None => unreachable!(),
+    pub fn map_values_in_place<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, V) -> V,
+        V: Default,
+    {
+        for (k, v) in self.iter_mut() {
+            *v = f(k, std::mem::take(v));
         }
     }
 
-    /// Returns a reference to the value corresponding to the key.
-    ///
-    /// The key may be any borrowed form of the map's key type, but
-    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
-    /// the key type.
-    ///
-    /// [`Eq`]: ../../std/cmp/trait.Eq.html
-    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    /// Returns the sum of all values in the map, or zero for an empty map.
     ///
     /// # Examples
     ///
@@ -599,31 +973,20 @@ where
     /// use halfbrown::HashMap;
     ///
     /// let mut map = HashMap::new();
-    /// map.insert(1, "a");
-    /// assert_eq!(map.get(&1), Some(&"a"));
-    /// assert_eq!(map.get(&2), None);
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// assert_eq!(map.sum_values(), 6);
     /// ```
-    #[inline]
-    pub fn get<Q: ?Sized>(&self, k: &Q) -> Option<&V>
+    #[must_use]
+    pub fn sum_values(&self) -> V
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
+        V: Sum + Copy,
     {
-        match &self.0 {
-            HashMapInt::Map(m) => m.get(k),
-            HashMapInt::Vec(m) => m.get(k),
-            HashMapInt::None => unreachable!(),
-        }
+        self.values().copied().sum()
     }
 
-    /// Returns `true` if the map contains a value for the specified key.
-    ///
-    /// The key may be any borrowed form of the map's key type, but
-    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
-    /// the key type.
-    ///
-    /// [`Eq`]: ../../std/cmp/trait.Eq.html
-    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    /// Returns the product of all values in the map, or one for an empty map.
     ///
     /// # Examples
     ///
@@ -631,30 +994,21 @@ where
     /// use halfbrown::HashMap;
     ///
     /// let mut map = HashMap::new();
-    /// map.insert(1, "a");
-    /// assert_eq!(map.contains_key(&1), true);
-    /// assert_eq!(map.contains_key(&2), false);
+    /// map.insert("a", 2);
+    /// map.insert("b", 3);
+    /// map.insert("c", 4);
+    /// assert_eq!(map.product_values(), 24);
     /// ```
-    pub fn contains_key<Q: ?Sized>(&self, k: &Q) -> bool
+    #[must_use]
+    pub fn product_values(&self) -> V
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
+        V: Product + Copy,
     {
-        match &self.0 {
-            HashMapInt::Map(m) => m.contains_key(k),
-            HashMapInt::Vec(m) => m.contains_key(k),
-            HashMapInt::None => unreachable!(),
-        }
+        self.values().copied().product()
     }
 
-    /// Returns a mutable reference to the value corresponding to the key.
-    ///
-    /// The key may be any borrowed form of the map's key type, but
-    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
-    /// the key type.
-    ///
-    /// [`Eq`]: ../../std/cmp/trait.Eq.html
-    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    /// Folds all values of the map into an accumulator, starting from
+    /// `init`, in arbitrary order.
     ///
     /// # Examples
     ///
@@ -662,37 +1016,22 @@ where
     /// use halfbrown::HashMap;
     ///
     /// let mut map = HashMap::new();
-    /// map.insert(1, "a");
-    /// if let Some(x) = map.get_mut(&1) {
-    ///     *x = "b";
-    /// }
-    /// assert_eq!(map[&1], "b");
+    /// map.insert("a", 3);
+    /// map.insert("b", 7);
+    /// map.insert("c", 5);
+    /// assert_eq!(map.fold_values(0, |max, &v| max.max(v)), 7);
     /// ```
-
-    #[inline]
-    pub fn get_mut<Q: ?Sized>(&mut self, k: &Q) -> Option<&mut V>
+    pub fn fold_values<B, F>(&self, init: B, f: F) -> B
     where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
+        F: FnMut(B, &V) -> B,
     {
-        match &mut self.0 {
-            HashMapInt::Map(m) => m.get_mut(k),
-            HashMapInt::Vec(m) => m.get_mut(k),
-            HashMapInt::None => unreachable!(),
-        }
+        self.values().fold(init, f)
     }
 
-    /// Inserts a key-value pair into the map.
-    ///
-    /// If the map did not have this key present, [`None`] is returned.
-    ///
-    /// If the map did have this key present, the value is updated, and the old
-    /// value is returned. The key is not updated, though; this matters for
-    /// types that can be `==` without being identical. See the [module-level
-    /// documentation] for more.
+    /// Counts how many keys map to each distinct value.
     ///
-    /// [`None`]: ../../std/option/enum.Option.html#variant.None
-    /// [module-level documentation]: index.html#insert-and-complex-keys
+    /// The counts start out in a vector backed map, they only get promoted
+    /// once there are more distinct values than a vector holds.
     ///
     /// # Examples
     ///
@@ -700,355 +1039,5404 @@ where
     /// use halfbrown::HashMap;
     ///
     /// let mut map = HashMap::new();
-    /// assert_eq!(map.insert(37, "a"), None);
-    /// assert_eq!(map.is_empty(), false);
+    /// map.insert("a", "red");
+    /// map.insert("b", "blue");
+    /// map.insert("c", "red");
     ///
-    /// map.insert(37, "b");
-    /// assert_eq!(map.insert(37, "c"), Some("b"));
-    /// assert_eq!(map[&37], "c");
+    /// let counts = map.value_counts();
+    /// assert_eq!(counts.len(), 2);
+    /// assert_eq!(counts["red"], 2);
+    /// assert_eq!(counts["blue"], 1);
     /// ```
-    #[inline]
-    pub fn insert(&mut self, k: K, v: V) -> Option<V>
+    #[must_use]
+    pub fn value_counts(&self) -> HashMap<V, usize>
     where
-        S: Default,
+        V: Eq + Hash + Clone,
     {
-        match &mut self.0 {
-            HashMapInt::Map(m) => m.insert(k, v),
-            HashMapInt::Vec(m) => {
-                if m.len() >= VEC_LIMIT_UPPER {
-                    let r;
-                    self.0 = match std::mem::replace(&mut self.0, HashMapInt::None) {
-                        HashMapInt::Vec(mut m) => {
-                            let mut m1: HashBrown<K, V, S> = m.drain().collect();
-                            r = m1.insert(k, v);
-                            HashMapInt::Map(m1)
-                        }
-                        _ => unreachable!(),
-                    };
-                    r
-                } else {
-                    m.insert(k, v)
-                }
+        let mut counts = HashMap::with_capacity(self.len().min(VEC_LIMIT_UPPER));
+        for v in self.values() {
+            if let Some(count) = counts.get_mut(v) {
+                *count += 1;
+            } else {
+                counts.insert(v.clone(), 1);
             }
-            HashMapInt::None => unreachable!(),
         }
+        counts
     }
 
-    /// Removes a key from the map, returning the value at the key if the key
-    /// was previously in the map.
-    ///
-    /// The key may be any borrowed form of the map's key type, but
-    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
-    /// the key type.
-    ///
-    /// [`Eq`]: ../../std/cmp/trait.Eq.html
-    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    /// Returns the number of elements in the map. This takes constant time
+    /// for both backends.
     ///
     /// # Examples
     ///
     /// ```
     /// use halfbrown::HashMap;
     ///
-    /// let mut map = HashMap::new();
-    /// map.insert(1, "a");
-    /// assert_eq!(map.remove(&1), Some("a"));
-    /// assert_eq!(map.remove(&1), None);
+    /// let mut a = HashMap::new();
+    /// assert_eq!(a.len(), 0);
+    /// a.insert(1, "a");
+    /// assert_eq!(a.len(), 1);
     /// ```
     #[inline]
-    pub fn remove<Q: ?Sized>(&mut self, k: &Q) -> Option<V>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq,
-    {
-        match &mut self.0 {
-            HashMapInt::Map(m) => m.remove(k),
-            HashMapInt::Vec(m) => m.remove(k),
-            HashMapInt::None => unreachable!(),
+    pub fn len(&self) -> usize {
+        match &self.0 {
+            HashMapInt::Map(m) => m.len(),
+            HashMapInt::Vec(m) => m.len(),
+            HashMapInt::None => none_state!(),
         }
     }
 
-    /// Retains only the elements specified by the predicate.
-    ///
-    /// In other words, remove all pairs `(k, v)` such that `f(&k, &mut v)` returns `false`.
-    /// The elements are visited in unsorted (and unspecified) order.
+    /// Returns `true` if the map contains no elements.
     ///
     /// # Examples
     ///
     /// ```
     /// use halfbrown::HashMap;
     ///
-    /// let mut map: HashMap<i32, i32> = (0..8).map(|x| (x, x*10)).collect();
-    /// map.retain(|&k, _| k % 2 == 0);
-    /// assert_eq!(map.len(), 4);
+    /// let mut a = HashMap::new();
+    /// assert!(a.is_empty());
+    /// a.insert(1, "a");
+    /// assert!(!a.is_empty());
     /// ```
     #[inline]
-    pub fn retain<F>(&mut self, f: F)
-    where
-        F: FnMut(&K, &mut V) -> bool,
-    {
+    pub fn is_empty(&self) -> bool {
+        match &self.0 {
+            HashMapInt::Map(m) => m.is_empty(),
+            HashMapInt::Vec(m) => m.is_empty(),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Clears the map, returning all key-value pairs as an iterator. Keeps the
+    /// allocated memory for reuse.
+    ///
+    /// A vector backed map yields its pairs in the order they were inserted,
+    /// as long as none were removed since removing a pair moves the last one
+    /// into its place. The map backend yields them in arbitrary order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut a = HashMap::new();
+    /// a.insert(1, "a");
+    /// a.insert(2, "b");
+    ///
+    /// for (k, v) in a.drain().take(1) {
+    ///     assert!(k == 1 || k == 2);
+    ///     assert!(v == "a" || v == "b");
+    /// }
+    ///
+    /// assert!(a.is_empty());
+    /// ```
+    #[inline]
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
         match &mut self.0 {
-            HashMapInt::Map(m) => m.retain(f),
-            HashMapInt::Vec(m) => m.retain(f),
-            HashMapInt::None => unreachable!(),
+            HashMapInt::Map(m) => Drain(DrainInt::Map(m.drain())),
+            HashMapInt::Vec(m) => Drain(DrainInt::Vec(m.drain())),
+            HashMapInt::None => none_state!(),
         }
     }
 
-    /// Inserts element, this ignores check in the vector
-    /// map if keys are present - it's a fast way to build
-    /// a new map when uniqueness is known ahead of time.
+    /// Clears the map, returning all key-value pairs as an iterator in the
+    /// order they are stored. This is the same as [`drain`], use it to make
+    /// relying on the order of a vector backed map explicit.
+    ///
+    /// [`drain`]: #method.drain
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut a = HashMap::new();
+    /// a.insert(3, "c");
+    /// a.insert(1, "a");
+    /// a.insert(2, "b");
+    ///
+    /// let pairs: Vec<_> = a.drain_in_order().collect();
+    /// assert_eq!(pairs, vec![(3, "c"), (1, "a"), (2, "b")]);
+    /// assert!(a.is_empty());
+    /// ```
     #[inline]
-    pub fn insert_nocheck(&mut self, k: K, v: V) {
+    pub fn drain_in_order(&mut self) -> Drain<'_, K, V> {
+        self.drain()
+    }
+
+    /// Clears the map, returning all key-value pairs sorted by key. Keeps
+    /// the allocated memory for reuse.
+    ///
+    /// Unlike [`drain`] all entries are moved out of the map right away, the
+    /// map is empty once this returns.
+    ///
+    /// [`drain`]: #method.drain
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut a = HashMap::new();
+    /// a.insert(3, "c");
+    /// a.insert(1, "a");
+    /// a.insert(2, "b");
+    ///
+    /// let sorted: Vec<_> = a.drain_sorted().collect();
+    /// assert_eq!(sorted, vec![(1, "a"), (2, "b"), (3, "c")]);
+    /// assert!(a.is_empty());
+    /// ```
+    pub fn drain_sorted(&mut self) -> impl ExactSizeIterator<Item = (K, V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<(K, V)> = self.drain().collect();
+        entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        entries.into_iter()
+    }
+
+    /// An iterator visiting all key-value pairs ordered by their keys
+    /// according to `compare`.
+    ///
+    /// This allows deterministic iteration for keys that aren't [`Ord`].
+    ///
+    /// [`Ord`]: https://doc.rust-lang.org/std/cmp/trait.Ord.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(3, "c");
+    /// map.insert(2, "b");
+    ///
+    /// let keys: Vec<_> = map.sorted_iter_by(|a, b| b.cmp(a)).map(|(k, _)| *k).collect();
+    /// assert_eq!(keys, [3, 2, 1]);
+    /// ```
+    pub fn sorted_iter_by<F>(&self, mut compare: F) -> impl ExactSizeIterator<Item = (&K, &V)>
+    where
+        F: FnMut(&K, &K) -> Ordering,
+    {
+        let mut entries: Vec<(&K, &V)> = self.iter().collect();
+        entries.sort_unstable_by(|(a, _), (b, _)| compare(a, b));
+        entries.into_iter()
+    }
+
+    /// An iterator visiting all key-value pairs ordered by their float keys
+    /// according to [`f64::total_cmp`].
+    ///
+    /// Negative NaNs come first and positive NaNs last, and `-0.0` comes
+    /// before `0.0`.
+    ///
+    /// [`f64::total_cmp`]: https://doc.rust-lang.org/std/primitive.f64.html#method.total_cmp
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// use std::borrow::Borrow;
+    /// use std::hash::{Hash, Hasher};
+    ///
+    /// // a float key compared by its bits
+    /// #[derive(Debug, Clone, Copy)]
+    /// struct Key(f64);
+    /// impl PartialEq for Key {
+    ///     fn eq(&self, other: &Self) -> bool {
+    ///         self.0.to_bits() == other.0.to_bits()
+    ///     }
+    /// }
+    /// impl Eq for Key {}
+    /// impl Hash for Key {
+    ///     fn hash<H: Hasher>(&self, state: &mut H) {
+    ///         self.0.to_bits().hash(state);
+    ///     }
+    /// }
+    /// impl Borrow<f64> for Key {
+    ///     fn borrow(&self) -> &f64 {
+    ///         &self.0
+    ///     }
+    /// }
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(Key(2.5), "b");
+    /// map.insert(Key(-1.0), "a");
+    /// map.insert(Key(10.0), "c");
+    ///
+    /// let values: Vec<_> = map.sorted_iter_by_total_cmp().map(|(_, v)| *v).collect();
+    /// assert_eq!(values, ["a", "b", "c"]);
+    /// ```
+    pub fn sorted_iter_by_total_cmp(&self) -> impl ExactSizeIterator<Item = (&K, &V)>
+    where
+        K: Borrow<f64>,
+    {
+        self.sorted_iter_by(|a, b| a.borrow().total_cmp(b.borrow()))
+    }
+
+    /// Clears the map, removing all key-value pairs. Keeps the allocated memory
+    /// for reuse.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut a = HashMap::new();
+    /// a.insert(1, "a");
+    /// a.clear();
+    /// assert!(a.is_empty());
+    /// ```
+    #[inline]
+    pub fn clear(&mut self) {
         match &mut self.0 {
-            HashMapInt::Map(m) => {
-                m.insert(k, v);
-            }
-            HashMapInt::Vec(m) => m.insert_nocheck(k, v),
-            HashMapInt::None => unreachable!(),
+            HashMapInt::Map(m) => m.clear(),
+            HashMapInt::Vec(m) => m.clear(),
+            HashMapInt::None => none_state!(),
         }
     }
 
-    /// Checks if the current backend is a map, if so returns
-    /// true.
-    pub fn is_map(&self) -> bool {
+    /// Clones the map into a vector backed map, regardless of the backend
+    /// of this map, as long as it fits into a vector. Maps with more than
+    /// [`VEC_LIMIT_UPPER`] elements are cloned as they are.
+    ///
+    /// This is useful to get the faster vector backend back for a map that
+    /// got promoted but has shrunk since.
+    ///
+    /// [`VEC_LIMIT_UPPER`]: constant.VEC_LIMIT_UPPER.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<i32, i32> = (0..100).map(|x| (x, x)).collect();
+    /// for k in 4..100 {
+    ///     map.remove(&k);
+    /// }
+    /// assert!(map.is_map());
+    ///
+    /// let small = map.clone_as_vec();
+    /// assert!(small.is_vec());
+    /// assert_eq!(small, map);
+    /// ```
+    #[must_use]
+    pub fn clone_as_vec(&self) -> Self
+    where
+        K: Clone,
+        V: Clone,
+        S: Clone,
+    {
         match &self.0 {
-            HashMapInt::Map(_m) => true,
-            HashMapInt::Vec(_m) => false,
-            HashMapInt::None => unreachable!(),
+            HashMapInt::Map(m) if m.len() <= self.1.vec_limit() => {
+                let mut v = VecMap::with_capacity_and_hasher(m.len(), m.hasher().clone());
+                for (k, val) in m {
+                    v.insert_nocheck(k.clone(), val.clone());
+                }
+                Self(HashMapInt::Vec(v), self.1.clone())
+            }
+            HashMapInt::Map(_) | HashMapInt::Vec(_) => self.clone(),
+            HashMapInt::None => none_state!(),
         }
     }
 
-    /// Checks if the current backend is a vector, if so returns
-    /// true.
-    pub fn is_vec(&self) -> bool {
-        match &self.0 {
-            HashMapInt::Map(_m) => false,
-            HashMapInt::Vec(_m) => true,
-            HashMapInt::None => unreachable!(),
+    /// Clones the map, picking the backend for the clone from its current
+    /// length instead of keeping the one of this map.
+    ///
+    /// Unlike [`clone_as_vec`] this respects [`hint_large`], a map hinted
+    /// to be large is cloned as it is. Otherwise a map backed map that fits
+    /// into a vector is cloned into a vector backed one.
+    ///
+    /// [`clone_as_vec`]: #method.clone_as_vec
+    /// [`hint_large`]: #method.hint_large
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<i32, i32> = (0..100).map(|x| (x, x)).collect();
+    /// for k in 4..100 {
+    ///     map.remove(&k);
+    /// }
+    /// assert!(map.clone_optimized().is_vec());
+    ///
+    /// map.hint_large();
+    /// assert!(map.clone_optimized().is_map());
+    /// ```
+    #[must_use]
+    pub fn clone_optimized(&self) -> Self
+    where
+        K: Clone,
+        V: Clone,
+        S: Clone,
+    {
+        if self.1.large() {
+            self.clone()
+        } else {
+            self.clone_as_vec()
         }
     }
-}
 
-impl<K, Q: ?Sized, V, S> Index<&Q> for HashMap<K, V, S>
-where
-    K: Eq + Hash + Borrow<Q>,
-    Q: Eq + Hash,
-    S: BuildHasher,
-{
-    type Output = V;
+    /// Returns the underlying `hashbrown` map if the map is map backed, or
+    /// `None` if it is vector backed.
+    ///
+    /// This is an escape hatch for interoperating with code that expects a
+    /// `hashbrown` map. Which backend is used for a given size, and the
+    /// version of `hashbrown` behind it, are implementation details that
+    /// can change between releases.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// assert!(map.as_hashbrown().is_none());
+    ///
+    /// map.hint_large();
+    /// assert_eq!(map.as_hashbrown().map(|m| m[&1]), Some("a"));
+    /// ```
+    #[must_use]
+    pub fn as_hashbrown(&self) -> Option<&HashBrown<K, V, S>> {
+        match &self.0 {
+            HashMapInt::Map(m) => Some(m),
+            HashMapInt::Vec(_) => None,
+            HashMapInt::None => none_state!(),
+        }
+    }
 
-    /// Returns a reference to the value corresponding to the supplied key.
+    /// Returns the key-value pairs of the underlying vector if the map is
+    /// vector backed, or `None` if it is map backed.
     ///
-    /// # Panics
+    /// Like [`as_hashbrown`] this exposes an implementation detail, the
+    /// order of the pairs is unspecified and can change with any mutation.
     ///
-    /// Panics if the key is not present in the `HashMap`.
-    #[inline]
-    fn index(&self, key: &Q) -> &V {
-        self.get(key).expect("no entry found for key")
+    /// [`as_hashbrown`]: #method.as_hashbrown
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.as_vec_pairs(), Some(&vec![(1, "a")]));
+    ///
+    /// map.hint_large();
+    /// assert!(map.as_vec_pairs().is_none());
+    /// ```
+    #[must_use]
+    pub fn as_vec_pairs(&self) -> Option<&Vec<(K, V)>> {
+        match &self.0 {
+            HashMapInt::Map(_) => None,
+            HashMapInt::Vec(m) => Some(m.as_vec()),
+            HashMapInt::None => none_state!(),
+        }
     }
 }
 
 impl<K, V, S> HashMap<K, V, S>
 where
-    S: BuildHasher,
     K: Eq + Hash,
+    S: BuildHasher,
 {
-    /// Creates a raw entry builder for the `HashMap`.
+    /// Reserves capacity for at least `additional` more elements to be inserted
+    /// in the `HashMap`. The collection may reserve more space to avoid
+    /// frequent reallocations.
     ///
-    /// Raw entries provide the lowest level of control for searching and
-    /// manipulating a map. They must be manually initialized with a hash and
-    /// then manually searched. After this, insertions into a vacant entry
-    /// still require an owned key to be provided.
+    /// Capacity reserved on the vector backend is carried over when the map
+    /// switches to the map backend. Reserving room for more elements than
+    /// fit into a vector hints the map to be large, like [`hint_large`], so
+    /// it never moves back to a vector. A map backed map that wasn't hinted
+    /// to be large and shrank enough for it and the `additional` elements to
+    /// fit into a vector is moved back to the vector backend, which gets a
+    /// clone of the hasher.
     ///
-    /// Raw entries are useful for such exotic situations as:
+    /// [`hint_large`]: #method.hint_large
     ///
-    /// * Hash memoization
-    /// * Deferring the creation of an owned key until it is known to be required
-    /// * Using a search key that doesn't work with the Borrow trait
-    /// * Using custom comparison logic without newtype wrappers
+    /// # Panics
     ///
-    /// Because raw entries provide much more low-level control, it's much easier
-    /// to put the `HashMap` into an inconsistent state which, while memory-safe,
-    /// will cause the map to produce seemingly random results. Higher-level and
-    /// more foolproof APIs like `entry` should be preferred when possible.
+    /// Panics if the new allocation size overflows [`usize`].
     ///
-    /// In particular, the hash used to initialized the raw entry must still be
-    /// consistent with the hash of the key that is ultimately stored in the entry.
-    /// This is because implementations of `HashMap` may need to recompute hashes
-    /// when resizing, at which point only the keys are available.
+    /// [`usize`]: ../../std/primitive.usize.html
     ///
-    /// Raw entries give mutable access to the keys. This must not be used
-    /// to modify how the key would compare or hash, as the map will not re-evaluate
-    /// where the key should go, meaning the keys may become "lost" if their
-    /// location does not reflect their state. For instance, if you change a key
-    /// so that the map now contains keys which compare equal, search may start
-    /// acting erratically, with two keys randomly masking each other. Implementations
-    /// are free to assume this doesn't happen (within the limits of memory-safety).
-    #[inline]
-    pub fn raw_entry_mut(&mut self) -> RawEntryBuilderMut<'_, K, V, S> {
-        match &mut self.0 {
-            HashMapInt::Vec(m) => RawEntryBuilderMut::from(m.raw_entry_mut()),
-            HashMapInt::Map(m) => RawEntryBuilderMut::from(m.raw_entry_mut()),
-            HashMapInt::None => unreachable!(),
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// let mut map: HashMap<&str, i32> = HashMap::new();
+    /// map.reserve(10);
+    /// assert!(map.is_vec());
+    /// map.reserve(100);
+    /// assert!(map.is_map());
+    ///
+    /// let mut map: HashMap<i32, i32> = (0..100).map(|x| (x, x)).collect();
+    /// for k in 4..100 {
+    ///     map.remove(&k);
+    /// }
+    /// assert!(map.is_map());
+    /// map.reserve(10);
+    /// assert!(map.is_vec());
+    /// ```
+    #[inline]
+    pub fn reserve(&mut self, additional: usize)
+    where
+        S: Clone,
+    {
+        let len = self.len().saturating_add(additional);
+        self.1.mark_large(len > self.1.vec_limit());
+        match &self.0 {
+            HashMapInt::Map(_) if !self.1.large() => self.swap_to_vec(additional),
+            _ => self.grow(additional),
+        }
+    }
+
+    /// Reserves room for `additional` more elements like [`reserve`], without
+    /// taking it as a hint that the map is going to be large. This is for
+    /// sizing the map ahead of inserts the map does on its own.
+    ///
+    /// [`reserve`]: #method.reserve
+    #[inline]
+    pub(crate) fn grow(&mut self, additional: usize) {
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.reserve(additional),
+            HashMapInt::Vec(m) if m.len().saturating_add(additional) > self.1.vec_limit() => {
+                self.swap_to_map(additional);
+            }
+            HashMapInt::Vec(m) => m.reserve(additional),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Sizes the map ahead of inserting a batch of up to `additional` pairs
+    /// whose keys may already be present.
+    ///
+    /// An empty map is sized for all of them like [`grow`] does. Otherwise
+    /// the vector backend is only grown up to its limit and left to be
+    /// promoted by `insert` once it actually outgrows it, and the map
+    /// backend reserves room for half of them, like `hashbrown`'s `extend`.
+    ///
+    /// [`grow`]: #method.grow
+    #[inline]
+    pub(crate) fn grow_for_batch(&mut self, additional: usize) {
+        if self.is_empty() {
+            return self.grow(additional);
+        }
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.reserve((additional + 1) / 2),
+            HashMapInt::Vec(m) => {
+                m.reserve(additional.min(self.1.vec_limit().saturating_sub(m.len())));
+            }
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Hints that this map is going to be large, switching it to the
+    /// map backend right away instead of waiting for it to outgrow the
+    /// vector. The map stays map backed from then on, so retaining fewer
+    /// entries or reserving room afterwards will not move it back to a vector.
+    ///
+    /// This moves the cost of promoting the map to where the hint is given,
+    /// during setup rather than on whichever insert crosses the limit. It
+    /// also finishes the [`vec_with_capacity`] and [`insert_nocheck`] fast
+    /// build path, duplicate keys are resolved the way [`insert`] would.
+    ///
+    /// [`vec_with_capacity`]: #method.vec_with_capacity
+    /// [`insert_nocheck`]: #method.insert_nocheck
+    /// [`insert`]: #method.insert
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// let mut map: HashMap<&str, i32> = HashMap::new();
+    /// map.hint_large();
+    /// assert!(map.is_map());
+    ///
+    /// let mut map = HashMap::vec_with_capacity(64);
+    /// for i in 0..64 {
+    ///     map.insert_nocheck(i, i);
+    /// }
+    /// map.hint_large();
+    /// assert!(map.is_map());
+    /// assert_eq!(map[&42], 42);
+    /// ```
+    #[inline]
+    pub fn hint_large(&mut self) {
+        self.1.mark_large(true);
+        self.swap_to_map(0);
+    }
+
+    /// Hints that about `additional` more elements are going to be inserted
+    /// after the map outgrows its vector. When that happens the map backend
+    /// reserves room for them right away, rather than growing step by step
+    /// while they get inserted.
+    ///
+    /// This doesn't do anything for a map that is already map backed, use
+    /// [`reserve`] for that.
+    ///
+    /// [`reserve`]: #method.reserve
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// let mut map = HashMap::new();
+    /// map.hint_additional(100);
+    /// for i in 0..33 {
+    ///     map.entry(i).or_insert(i);
+    /// }
+    /// assert!(map.is_map());
+    /// assert!(map.capacity() >= 133);
+    /// ```
+    #[inline]
+    pub fn hint_additional(&mut self, additional: usize) {
+        self.1.set_headroom(additional);
+    }
+
+    /// Moves the content of a vector backend into a map backend with
+    /// room for `additional`, or the hinted headroom if that is larger,
+    /// more elements.
+    ///
+    /// The keys are hashed while they still sit in the vector and the new
+    /// backend is put in place before it gets filled, so a panic in user
+    /// code, or in the allocator, can never leave the map in the transient
+    /// `None` state. At worst entries that were being moved are lost.
+    fn swap_to_map(&mut self, additional: usize) {
+        let additional = additional.max(self.1.headroom());
+        let (hashes, reserved): (Vec<u64>, usize) = match &self.0 {
+            HashMapInt::Vec(m) => (
+                m.iter().map(|(k, _)| m.hasher().hash_one(k)).collect(),
+                m.capacity(),
+            ),
+            _ => return,
+        };
+        let v = match std::mem::replace(&mut self.0, HashMapInt::None) {
+            HashMapInt::Vec(m) => {
+                let (v, hash_builder) = m.into_parts();
+                self.0 = HashMapInt::Map(HashBrown::with_hasher(hash_builder));
+                v
+            }
+            _ => unreachable!(),
+        };
+        #[cfg(feature = "metrics")]
+        metrics::promoted();
+        if let HashMapInt::Map(m) = &mut self.0 {
+            // whatever was reserved on the vector carries over to the map
+            m.reserve(v.len().saturating_add(additional).max(reserved));
+            insert_hashed(m, v, hashes);
+        }
+    }
+
+    /// Moves the content of a map backend into a vector backend with room
+    /// for `additional` more elements.
+    ///
+    /// The vector is allocated before anything is moved and moving runs no
+    /// user code, so a panic leaves the map backend in place. `hashbrown`
+    /// doesn't give up its hasher, the vector gets a clone of it so hashes
+    /// stay the same.
+    fn swap_to_vec(&mut self, additional: usize)
+    where
+        S: Clone,
+    {
+        if let HashMapInt::Map(m) = &mut self.0 {
+            let mut v = VecMap::with_capacity_and_hasher(
+                m.len().saturating_add(additional),
+                m.hasher().clone(),
+            );
+            for (k, val) in m.drain() {
+                v.insert_nocheck(k, val);
+            }
+            self.0 = HashMapInt::Vec(v);
+            #[cfg(feature = "metrics")]
+            metrics::demoted();
+        }
+    }
+
+    /// Moves a map backed map that shrank enough to fit into a vector back
+    /// to the vector backend, unless it was hinted to be large.
+    fn maybe_downgrade(&mut self)
+    where
+        S: Clone,
+    {
+        if let HashMapInt::Map(m) = &self.0 {
+            if !self.1.large() && m.len() <= self.1.vec_limit() {
+                self.swap_to_vec(0);
+            }
+        }
+    }
+
+    /// Like `swap_to_map` but leaves the vector backend in place if the
+    /// map backend can't be allocated.
+    ///
+    /// The vector has to keep its hasher until the map is allocated, and
+    /// `hashbrown` can't hand it back if that fails, so the map gets a
+    /// clone of it rather than the hasher itself.
+    fn try_swap_to_map(&mut self, additional: usize) -> Result<(), TryReserveError>
+    where
+        S: Clone,
+    {
+        let additional = additional.max(self.1.headroom());
+        if let HashMapInt::Vec(m) = &mut self.0 {
+            let mut m1 = HashBrown::with_hasher(m.hasher().clone());
+            m1.try_reserve(m.len().saturating_add(additional).max(m.capacity()))?;
+            let hashes: Vec<u64> = m.iter().map(|(k, _)| m1.hasher().hash_one(k)).collect();
+            insert_hashed(&mut m1, m.drain(), hashes);
+            self.0 = HashMapInt::Map(m1);
+            #[cfg(feature = "metrics")]
+            metrics::promoted();
+        }
+        Ok(())
+    }
+
+    /// Tries to reserve capacity for at least `additional` more elements to be inserted
+    /// in the given `HashMap<K,V>`. The collection may reserve more space to avoid
+    /// frequent reallocations.
+    ///
+    /// Like [`reserve`] this promotes a vector backed map that would grow
+    /// past the vector limit, if that fails the map stays vector backed.
+    /// A successful reservation past the vector limit hints the map to be
+    /// large, the same as with [`reserve`].
+    ///
+    /// [`reserve`]: #method.reserve
+    ///
+    /// # Errors
+    ///
+    /// If the capacity overflows, or the allocator reports a failure, then an error
+    /// is returned and the map is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// let mut map: HashMap<&str, isize> = HashMap::new();
+    /// map.try_reserve(10).expect("why is the test harness OOMing on 10 bytes?");
+    /// assert!(map.is_vec());
+    /// map.try_reserve(100).expect("why is the test harness OOMing on 100 bytes?");
+    /// assert!(map.is_map());
+    /// assert!(map.try_reserve(usize::MAX).is_err());
+    /// ```
+    #[inline]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError>
+    where
+        S: Clone,
+    {
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.try_reserve(additional)?,
+            HashMapInt::Vec(m) if m.len().saturating_add(additional) > self.1.vec_limit() => {
+                self.try_swap_to_map(additional)?;
+            }
+            HashMapInt::Vec(m) => m.try_reserve(additional)?,
+            HashMapInt::None => none_state!(),
+        }
+        self.1
+            .mark_large(self.len().saturating_add(additional) > self.1.vec_limit());
+        Ok(())
+    }
+
+    /// Shrinks the capacity of the map as much as possible. It will drop
+    /// down as much as possible while maintaining the internal rules
+    /// and possibly leaving some space in accordance with the resize policy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<i32, i32> = HashMap::with_capacity(100);
+    /// map.insert(1, 2);
+    /// map.insert(3, 4);
+    /// assert!(map.capacity() >= 100);
+    /// map.shrink_to_fit();
+    /// assert!(map.capacity() >= 2);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.shrink_to_fit(),
+            HashMapInt::Vec(m) => m.shrink_to_fit(),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Shrinks the capacity of the map with a lower limit. It will drop
+    /// down no lower than the supplied limit while maintaining the internal
+    /// rules and possibly leaving some space in accordance with the resize
+    /// policy.
+    ///
+    /// If the current capacity is less than the lower limit, this is a no-op.
+    /// The backend stays the same.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<i32, i32> = HashMap::with_capacity(100);
+    /// map.insert(1, 2);
+    /// map.insert(3, 4);
+    /// assert!(map.capacity() >= 100);
+    /// map.shrink_to(10);
+    /// assert!(map.capacity() >= 10);
+    /// map.shrink_to(0);
+    /// assert!(map.capacity() >= 2);
+    /// ```
+    #[inline]
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.shrink_to(min_capacity),
+            HashMapInt::Vec(m) => m.shrink_to(min_capacity),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Shrinks the capacity of the map as much as possible like
+    /// [`shrink_to_fit`], first removing duplicate keys [`insert_nocheck`]
+    /// may have added to a vector backed map so the capacity reflects the
+    /// unique keys. For every key the first value inserted, the one
+    /// lookups return, is kept.
+    ///
+    /// Looking for duplicates is skipped if `insert_nocheck` wasn't used on
+    /// the vector since they were last removed.
+    ///
+    /// [`shrink_to_fit`]: #method.shrink_to_fit
+    /// [`insert_nocheck`]: #method.insert_nocheck
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<i32, i32> = HashMap::with_capacity(8);
+    /// map.insert_nocheck(1, 2);
+    /// map.insert_nocheck(3, 4);
+    /// map.insert_nocheck(1, 5);
+    /// assert_eq!(map.len(), 3);
+    /// map.shrink_to_fit_dedup();
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map[&1], 2);
+    /// ```
+    pub fn shrink_to_fit_dedup(&mut self) {
+        self.dedup();
+        self.shrink_to_fit();
+    }
+
+    /// Moves the map to the backend that suits its length best and shrinks
+    /// it as much as possible. This removes duplicate keys like
+    /// [`shrink_to_fit_dedup`], moves map backed maps that fit into a vector
+    /// back to the vector backend, unless they were hinted to be large, and
+    /// promotes vectors [`insert_nocheck`] grew past the limit.
+    ///
+    /// [`shrink_to_fit_dedup`]: #method.shrink_to_fit_dedup
+    /// [`insert_nocheck`]: #method.insert_nocheck
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<i32, i32> = (0..100).map(|i| (i, i)).collect();
+    /// for i in 4..100 {
+    ///     map.remove(&i);
+    /// }
+    /// assert!(map.is_map());
+    /// map.compact();
+    /// assert!(map.is_vec());
+    /// assert_eq!(map.capacity(), 4);
+    /// ```
+    pub fn compact(&mut self)
+    where
+        S: Clone,
+    {
+        self.dedup();
+        match &self.0 {
+            HashMapInt::Vec(m) if m.len() > self.1.vec_limit() => self.swap_to_map(0),
+            HashMapInt::Map(_) => self.maybe_downgrade(),
+            HashMapInt::Vec(_) => (),
+            HashMapInt::None => none_state!(),
+        }
+        self.shrink_to_fit();
+    }
+
+    /// Removes duplicate keys `insert_nocheck` may have added to the vector
+    /// backend, keeping the first value for every key. That's the value
+    /// lookups return, and the one promoting the map keeps as well.
+    fn dedup(&mut self) {
+        if let HashMapInt::Vec(m) = &mut self.0 {
+            if self.1.maybe_duplicates() {
+                m.dedup();
+                self.1.set_maybe_duplicates(false);
+            }
+        }
+    }
+
+    /// Gets the given key's corresponding entry in the map for in-place manipulation.
+    ///
+    /// For the map backend the key is hashed and probed for once, the entry
+    /// keeps the hash so modifying or filling it never looks the key up
+    /// again. Only growing the table, promoting a full vector, or turning
+    /// a vacant entry into an occupied one with [`VacantEntry::insert_entry`]
+    /// hashes keys again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut letters = HashMap::new();
+    ///
+    /// for ch in "a short treatise on fungi".chars() {
+    ///     let counter = letters.entry(ch).or_insert(0);
+    ///     *counter += 1;
+    /// }
+    ///
+    /// assert_eq!(letters[&'s'], 2);
+    /// assert_eq!(letters[&'t'], 3);
+    /// assert_eq!(letters[&'u'], 1);
+    /// assert_eq!(letters.get(&'y'), None);
+    /// ```
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        // Only inserting a new key into a full vector promotes it, that
+        // happens once the vacant entry is filled, not here.
+        if let HashMapInt::Vec(m) = &self.0 {
+            if m.len() >= self.1.vec_limit() {
+                return match m.position(|k| *k == key) {
+                    Some(idx) => match &mut self.0 {
+                        HashMapInt::Vec(m) => {
+                            vecmap::Entry::Occupied(vecmap::OccupiedEntry::new(idx, key, m)).into()
+                        }
+                        _ => unreachable!(),
+                    },
+                    None => Entry::Vacant(VacantEntry::promoting(key, self)),
+                };
+            }
+        }
+        match &mut self.0 {
+            HashMapInt::Map(m) => {
+                let len = m.len();
+                let hash = m.hasher().hash_one(&key);
+                let entry = m.raw_entry_mut().from_key_hashed_nocheck(hash, &key);
+                Entry::from_map(entry, key, hash, len)
+            }
+            HashMapInt::Vec(m) => m.entry(key).into(),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Increments the counter stored for `k` by one, starting at the
+    /// default (zero) when the key wasn't present yet, and returns a
+    /// reference to the updated counter.
+    ///
+    /// Any type that can be created from a `u8` works as counter, that is
+    /// all the numeric primitives except `i8`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut words: HashMap<&str, usize> = HashMap::new();
+    /// for word in "the cat sat on the mat with the hat".split(' ') {
+    ///     words.bump(word);
+    /// }
+    ///
+    /// assert_eq!(words["the"], 3);
+    /// assert_eq!(words["cat"], 1);
+    /// assert_eq!(*words.bump("cat"), 2);
+    /// ```
+    #[inline]
+    pub fn bump(&mut self, k: K) -> &mut V
+    where
+        V: Default + AddAssign + From<u8>,
+    {
+        let counter = self.entry(k).or_insert_with(V::default);
+        *counter += V::from(1);
+        counter
+    }
+
+    /// Returns a mutable reference to the value of `k`, inserting the result
+    /// of `f` first if the key isn't present.
+    ///
+    /// If `f` fails the error is returned and the map is left unchanged, a
+    /// full vector backed map isn't promoted either.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `f` if the key was missing and `f` failed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut sizes: HashMap<&str, usize> = HashMap::new();
+    ///
+    /// let size = sizes.get_or_try_insert_with("lib", || "42".parse::<usize>());
+    /// assert_eq!(size, Ok(&mut 42));
+    /// let size = sizes.get_or_try_insert_with("lib", || "oops".parse::<usize>());
+    /// assert_eq!(size, Ok(&mut 42));
+    ///
+    /// assert!(sizes.get_or_try_insert_with("bin", || "oops".parse::<usize>()).is_err());
+    /// assert_eq!(sizes.len(), 1);
+    /// ```
+    pub fn get_or_try_insert_with<F, E>(&mut self, k: K, f: F) -> Result<&mut V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        match self.entry(k) {
+            Entry::Occupied(e) => Ok(e.into_mut()),
+            Entry::Vacant(e) => Ok(e.insert(f()?)),
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: ../../std/cmp/trait.Eq.html
+    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// assert_eq!(map.get(&2), None);
+    /// ```
+    #[inline]
+    pub fn get<Q: ?Sized>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match &self.0 {
+            HashMapInt::Map(m) => m.get(k),
+            HashMapInt::Vec(m) => m.get(k),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key, looked up
+    /// either by a plain borrow or by a [`HashedKey`].
+    ///
+    /// A precomputed hash lets the map backend skip hashing the key again,
+    /// the vec backend doesn't hash keys and only uses the borrow. The same
+    /// rules as for [`get`] apply to the borrowed form of the key.
+    ///
+    /// [`get`]: #method.get
+    /// [`HashedKey`]: struct.HashedKey.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("a".to_string(), 1);
+    /// assert_eq!(map.lookup("a"), Some(&1));
+    ///
+    /// let key = map.hashed_key("a");
+    /// assert_eq!(map.lookup(key), Some(&1));
+    /// assert_eq!(map.lookup("b"), None);
+    /// ```
+    #[inline]
+    pub fn lookup<'q, Q: ?Sized + 'q>(&self, q: impl Into<LookupKey<'q, Q>>) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match (&self.0, q.into()) {
+            (HashMapInt::Map(m), LookupKey::Hashed(k)) => m
+                .raw_entry()
+                .from_key_hashed_nocheck(k.hash(), k.key())
+                .map(|(_, v)| v),
+            (HashMapInt::Map(m), LookupKey::Borrowed(k)) => m.get(k),
+            (HashMapInt::Vec(m), q) => m.get(q.key()),
+            (HashMapInt::None, _) => none_state!(),
+        }
+    }
+
+    /// Hashes `k` with the map's hasher so it can be looked up repeatedly
+    /// with [`lookup`] without hashing it again.
+    ///
+    /// The hash stays valid for as long as the hasher does, so it can be
+    /// reused across backend changes and for clones of the map.
+    ///
+    /// [`lookup`]: #method.lookup
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let map: HashMap<u32, u32> = (0..100).map(|i| (i, i * 2)).collect();
+    /// let key = map.hashed_key(&7);
+    /// assert_eq!(map.lookup(key), Some(&14));
+    /// ```
+    #[inline]
+    pub fn hashed_key<'q, Q: ?Sized>(&self, k: &'q Q) -> HashedKey<'q, Q>
+    where
+        K: Borrow<Q>,
+        Q: Hash,
+    {
+        HashedKey::new(self.hasher().hash_one(k), k)
+    }
+
+    /// Returns the key-value pair corresponding to the supplied key.
+    ///
+    /// The supplied key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: ../../std/cmp/trait.Eq.html
+    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get_key_value(&1), Some((&1, &"a")));
+    /// assert_eq!(map.get_key_value(&2), None);
+    /// ```
+    #[inline]
+    pub fn get_key_value<Q: ?Sized>(&self, k: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match &self.0 {
+            HashMapInt::Map(m) => m.get_key_value(k),
+            HashMapInt::Vec(m) => m.get_key_value(k),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Returns a clone of the value corresponding to the key, or the default
+    /// value if the key isn't in the map. Nothing is inserted for missing
+    /// keys.
+    ///
+    /// The key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: ../../std/cmp/trait.Eq.html
+    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, 10);
+    /// assert_eq!(map.get_cloned_or_default(&1), 10);
+    /// assert_eq!(map.get_cloned_or_default(&2), 0);
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    #[inline]
+    pub fn get_cloned_or_default<Q: ?Sized>(&self, k: &Q) -> V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+        V: Default + Clone,
+    {
+        self.get(k).cloned().unwrap_or_default()
+    }
+
+    /// Hints the CPU to pull the memory `k` would be stored in into the
+    /// cache ahead of an upcoming lookup, so a batch of lookups can be
+    /// pipelined.
+    ///
+    /// For the map backend this prefetches the group of control bytes the
+    /// probe for `k` starts at and the bucket next to it. It only has an
+    /// effect for the map backend when the `prefetch` feature is enabled and
+    /// the target has a prefetch instruction, in every other case it is a
+    /// no-op.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// map.prefetch(&1);
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// ```
+    #[inline]
+    pub fn prefetch<Q: ?Sized>(&self, k: &Q)
+    where
+        Q: Hash,
+    {
+        #[cfg(feature = "prefetch")]
+        if let HashMapInt::Map(m) = &self.0 {
+            prefetch_hash(m, m.hasher().hash_one(k));
+        }
+        #[cfg(not(feature = "prefetch"))]
+        let _ = k;
+    }
+
+    /// Looks up a batch of keys at once, returning the values in the same
+    /// order as the keys.
+    ///
+    /// For the map backend all keys are hashed first, then the memory every
+    /// probe starts at is prefetched like [`prefetch`] does, and only then
+    /// the keys are probed for, so the cache misses of the batch overlap
+    /// instead of being taken one after the other. This pays off for maps
+    /// that don't fit into the cache, without the `prefetch` feature the
+    /// prefetch step is skipped.
+    ///
+    /// [`prefetch`]: #method.prefetch
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// assert_eq!(map.get_batched(&[&2, &3, &1]), vec![Some(&"b"), None, Some(&"a")]);
+    /// ```
+    pub fn get_batched<Q: ?Sized>(&self, keys: &[&Q]) -> Vec<Option<&V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match &self.0 {
+            HashMapInt::Map(m) => {
+                let hashes: Vec<u64> = keys.iter().map(|k| m.hasher().hash_one(k)).collect();
+                #[cfg(feature = "prefetch")]
+                for hash in &hashes {
+                    prefetch_hash(m, *hash);
+                }
+                keys.iter()
+                    .zip(hashes)
+                    .map(|(k, hash)| {
+                        m.raw_entry()
+                            .from_hash(hash, |q| q.borrow() == *k)
+                            .map(|(_, v)| v)
+                    })
+                    .collect()
+            }
+            HashMapInt::Vec(m) => keys.iter().map(|k| m.get(*k)).collect(),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: ../../std/cmp/trait.Eq.html
+    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.contains_key(&1), true);
+    /// assert_eq!(map.contains_key(&2), false);
+    /// ```
+    pub fn contains_key<Q: ?Sized>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match &self.0 {
+            HashMapInt::Map(m) => m.contains_key(k),
+            HashMapInt::Vec(m) => m.contains_key(k),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: ../../std/cmp/trait.Eq.html
+    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// if let Some(x) = map.get_mut(&1) {
+    ///     *x = "b";
+    /// }
+    /// assert_eq!(map[&1], "b");
+    /// ```
+    #[inline]
+    pub fn get_mut<Q: ?Sized>(&mut self, k: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.get_mut(k),
+            HashMapInt::Vec(m) => m.get_mut(k),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Looks up `N` keys at once, returning their stored keys together with
+    /// mutable references to their values.
+    ///
+    /// Returns `None` if any of the keys is missing or if the same entry
+    /// would be returned more than once, as the values couldn't be borrowed
+    /// mutably at the same time then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("a".to_string(), 1);
+    /// map.insert("b".to_string(), 2);
+    ///
+    /// if let Some([(ka, a), (kb, b)]) = map.get_many_key_value_mut(["a", "b"]) {
+    ///     assert_eq!(ka, "a");
+    ///     assert_eq!(kb, "b");
+    ///     std::mem::swap(a, b);
+    /// }
+    /// assert_eq!(map["a"], 2);
+    /// assert_eq!(map["b"], 1);
+    ///
+    /// assert!(map.get_many_key_value_mut(["a", "a"]).is_none());
+    /// assert!(map.get_many_key_value_mut(["a", "c"]).is_none());
+    /// ```
+    pub fn get_many_key_value_mut<Q: ?Sized, const N: usize>(
+        &mut self,
+        ks: [&Q; N],
+    ) -> Option<[(&K, &mut V); N]>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.get_many_key_value_mut(ks),
+            HashMapInt::Vec(m) => {
+                let kvs = m.get_many_at_mut(Self::vec_positions(m, ks)?)?;
+                Some(kvs.map(|(k, v)| (&*k, v)))
+            }
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Looks up `N` keys at once, returning mutable references to their
+    /// values.
+    ///
+    /// Returns `None` if any of the keys is missing or if the same entry
+    /// would be returned more than once, like [`get_many_key_value_mut`].
+    ///
+    /// [`get_many_key_value_mut`]: #method.get_many_key_value_mut
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    ///
+    /// if let Some([a, b]) = map.get_many_mut(["a", "b"]) {
+    ///     *a += 10;
+    ///     *b += 20;
+    /// }
+    /// assert_eq!(map["a"], 11);
+    /// assert_eq!(map["b"], 22);
+    ///
+    /// assert!(map.get_many_mut(["a", "a"]).is_none());
+    /// assert!(map.get_many_mut(["a", "c"]).is_none());
+    /// ```
+    pub fn get_many_mut<Q: ?Sized, const N: usize>(&mut self, ks: [&Q; N]) -> Option<[&mut V; N]>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.get_many_mut(ks),
+            HashMapInt::Vec(m) => {
+                let kvs = m.get_many_at_mut(Self::vec_positions(m, ks)?)?;
+                Some(kvs.map(|(_, v)| v))
+            }
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Finds the positions of `ks` in a vector backend, `None` if any of
+    /// them is missing.
+    fn vec_positions<Q: ?Sized, const N: usize>(
+        m: &VecMap<K, V, S>,
+        ks: [&Q; N],
+    ) -> Option<[usize; N]>
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+    {
+        let mut idxs = [0; N];
+        for (idx, k) in idxs.iter_mut().zip(ks) {
+            *idx = m.position(|stored| stored.borrow() == k)?;
+        }
+        Some(idxs)
+    }
+
+    /// Swaps the values of the keys `a` and `b`.
+    ///
+    /// Returns `true` if both keys were present and the values were swapped,
+    /// the map is left untouched otherwise. Swapping a key with itself does
+    /// nothing and returns whether the key is present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    ///
+    /// assert!(map.swap_values("a", "b"));
+    /// assert_eq!(map["a"], 2);
+    /// assert_eq!(map["b"], 1);
+    ///
+    /// assert!(map.swap_values("a", "a"));
+    /// assert!(!map.swap_values("a", "c"));
+    /// assert_eq!(map["a"], 2);
+    /// ```
+    pub fn swap_values<Q: ?Sized>(&mut self, a: &Q, b: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        if a == b {
+            return self.contains_key(a);
+        }
+        match self.get_many_mut([a, b]) {
+            Some([a, b]) => {
+                std::mem::swap(a, b);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Inserts a key-value pair into the map.
+    ///
+    /// If the map did not have this key present, [`None`] is returned.
+    ///
+    /// If the map did have this key present, the value is updated, and the old
+    /// value is returned. The key is not updated, though; this matters for
+    /// types that can be `==` without being identical. See the [module-level
+    /// documentation] for more.
+    ///
+    /// [`None`]: ../../std/option/enum.Option.html#variant.None
+    /// [module-level documentation]: index.html#insert-and-complex-keys
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// assert_eq!(map.insert(37, "a"), None);
+    /// assert_eq!(map.is_empty(), false);
+    ///
+    /// map.insert(37, "b");
+    /// assert_eq!(map.insert(37, "c"), Some("b"));
+    /// assert_eq!(map[&37], "c");
+    /// ```
+    #[inline]
+    pub fn insert(&mut self, k: K, v: V) -> Option<V>
+    where
+        S: Default,
+    {
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.insert(k, v),
+            HashMapInt::Vec(m) if m.len() < self.1.vec_limit() => m.insert(k, v),
+            HashMapInt::Vec(_) => self.promote_and_insert(k, v),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Promotes a full vector and inserts into the new map. This is kept out
+    /// of line so inserting into a small map only pays for a comparison that
+    /// is predicted right every time.
+    #[cold]
+    #[inline(never)]
+    fn promote_and_insert(&mut self, k: K, v: V) -> Option<V> {
+        self.swap_to_map(1);
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.insert(k, v),
+            HashMapInt::Vec(_) => unreachable!(),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Inserts a key-value pair into the map like [`insert`], also reporting
+    /// if the insert promoted a vector backed map to the map backend.
+    ///
+    /// [`insert`]: #method.insert
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::{HashMap, VEC_LIMIT_UPPER};
+    ///
+    /// let mut map = HashMap::new();
+    /// for i in 0..VEC_LIMIT_UPPER {
+    ///     assert_eq!(map.insert_reporting(i, i), (None, false));
+    /// }
+    /// assert_eq!(map.insert_reporting(VEC_LIMIT_UPPER, 0), (None, true));
+    /// assert!(map.is_map());
+    /// ```
+    #[inline]
+    pub fn insert_reporting(&mut self, k: K, v: V) -> (Option<V>, bool)
+    where
+        S: Default,
+    {
+        let was_vec = self.is_vec();
+        let old = self.insert(k, v);
+        (old, was_vec && self.is_map())
+    }
+
+    /// Inserts a key-value pair into the map unless that would make it hold
+    /// more than `max` elements.
+    ///
+    /// Updating the value of a key that is already present always succeeds
+    /// and returns the old value like [`insert`]. If the key is new and the
+    /// map already holds `max` elements, the pair is handed back as `Err`.
+    ///
+    /// [`insert`]: #method.insert
+    ///
+    /// # Errors
+    ///
+    /// Returns the rejected key-value pair if inserting it would exceed `max`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// assert_eq!(map.insert_bounded(1, "a", 2), Ok(None));
+    /// assert_eq!(map.insert_bounded(2, "b", 2), Ok(None));
+    /// assert_eq!(map.insert_bounded(3, "c", 2), Err((3, "c")));
+    /// assert_eq!(map.insert_bounded(1, "d", 2), Ok(Some("a")));
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    #[inline]
+    pub fn insert_bounded(&mut self, k: K, v: V, max: usize) -> Result<Option<V>, (K, V)>
+    where
+        S: Default,
+    {
+        if self.len() >= max && !self.contains_key(&k) {
+            Err((k, v))
+        } else {
+            Ok(self.insert(k, v))
+        }
+    }
+
+    /// Inserts a key-value pair into the map if the key isn't present yet.
+    ///
+    /// Returns `true` if the pair was inserted. If the map already had the
+    /// key `false` is returned, the value in the map is left untouched and
+    /// `v` is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// assert!(map.insert_if_absent(37, "a"));
+    /// assert!(!map.insert_if_absent(37, "b"));
+    /// assert_eq!(map[&37], "a");
+    /// ```
+    #[inline]
+    pub fn insert_if_absent(&mut self, k: K, v: V) -> bool {
+        match self.entry(k) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(e) => {
+                e.insert(v);
+                true
+            }
+        }
+    }
+
+    /// Applies a batch of updates: for every pair of `updates` the value is
+    /// inserted if its key is absent, otherwise `combine` is called with the
+    /// stored value and the new one.
+    ///
+    /// An empty map is sized for the lower size bound of `updates` before
+    /// the first update, so it's promoted once up front if they don't fit
+    /// into a vector. Updates to a map that already holds keys may not add
+    /// any, so it's only promoted once it actually outgrows the vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut stock: HashMap<&str, u32> = HashMap::new();
+    /// stock.insert("cap", 2);
+    /// stock.upsert_many(vec![("cap", 3), ("gill", 1), ("gill", 4)], |have, add| *have += add);
+    /// assert_eq!(stock["cap"], 5);
+    /// assert_eq!(stock["gill"], 5);
+    /// ```
+    pub fn upsert_many<I, U>(&mut self, updates: I, mut combine: U)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        U: FnMut(&mut V, V),
+    {
+        let updates = updates.into_iter();
+        self.grow_for_batch(updates.size_hint().0);
+        for (k, v) in updates {
+            match self.entry(k) {
+                Entry::Occupied(mut e) => combine(e.get_mut(), v),
+                Entry::Vacant(e) => {
+                    e.insert(v);
+                }
+            }
+        }
+    }
+
+    /// Inserts a key-value pair into the map, like [`insert`] does, but
+    /// returns an error instead of panicking when the map can't grow to
+    /// hold the new element.
+    ///
+    /// Replacing the value of a key that is already present never needs to
+    /// grow the map and so always succeeds.
+    ///
+    /// [`insert`]: #method.insert
+    ///
+    /// # Errors
+    ///
+    /// If the capacity overflows, or the allocator reports a failure, then an error
+    /// is returned and the map is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// assert_eq!(map.checked_insert(37, "a"), Ok(None));
+    /// assert_eq!(map.checked_insert(37, "b"), Ok(Some("a")));
+    /// assert_eq!(map[&37], "b");
+    /// ```
+    pub fn checked_insert(&mut self, k: K, v: V) -> Result<Option<V>, TryReserveError>
+    where
+        S: Clone,
+    {
+        if let Some(old) = self.get_mut(&k) {
+            return Ok(Some(std::mem::replace(old, v)));
+        }
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.try_reserve(1)?,
+            HashMapInt::Vec(m) if m.len() >= self.1.vec_limit() => self.try_swap_to_map(1)?,
+            HashMapInt::Vec(m) => m.try_reserve(1)?,
+            HashMapInt::None => none_state!(),
+        }
+        // the key is known to be absent, so there's no duplicate to flag
+        match &mut self.0 {
+            HashMapInt::Map(m) => {
+                m.insert(k, v);
+            }
+            HashMapInt::Vec(m) => m.insert_nocheck(k, v),
+            HashMapInt::None => none_state!(),
+        }
+        Ok(None)
+    }
+
+    /// Copies all pairs of `slice` into the map.
+    ///
+    /// An empty map is sized for the whole slice up front. A map that
+    /// already holds keys may only get updates from the slice, so it's
+    /// promoted once it actually outgrows the vector, and then sized for
+    /// the rest of the slice in one go. Pairs are
+    /// inserted like [`insert`] would, keys already in the map or repeated
+    /// in the slice are not duplicated and end up with the last value given
+    /// for them.
+    ///
+    /// [`insert`]: #method.insert
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("a", 1);
+    /// map.extend_from_slice(&[("a", 2), ("b", 3), ("b", 4)]);
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map["a"], 2);
+    /// assert_eq!(map["b"], 4);
+    /// ```
+    pub fn extend_from_slice(&mut self, slice: &[(K, V)])
+    where
+        K: Copy,
+        V: Copy,
+    {
+        self.grow_for_batch(slice.len());
+        for (i, &(k, v)) in slice.iter().enumerate() {
+            match &mut self.0 {
+                HashMapInt::Map(m) => {
+                    m.insert(k, v);
+                }
+                HashMapInt::Vec(m) if m.len() < self.1.vec_limit() => {
+                    m.insert(k, v);
+                }
+                HashMapInt::Vec(_) => {
+                    // sized for the rest of the slice in one go
+                    self.swap_to_map(slice.len() - i);
+                    self.insert_nocheck(k, v);
+                }
+                HashMapInt::None => none_state!(),
+            }
+        }
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key
+    /// was previously in the map.
+    ///
+    /// The key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: ../../std/cmp/trait.Eq.html
+    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.remove(&1), Some("a"));
+    /// assert_eq!(map.remove(&1), None);
+    /// ```
+    #[inline]
+    pub fn remove<Q: ?Sized>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.remove(k),
+            HashMapInt::Vec(m) => m.remove(k),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Replaces the key stored for an equal `key` with `key`, returning the
+    /// key that was stored, or `None` if no equal key is in the map.
+    ///
+    /// This is useful to make the map hold a shared key, like an `Rc` that
+    /// is already stored elsewhere, instead of an equal copy of it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// use std::rc::Rc;
+    ///
+    /// let mut map: HashMap<Rc<String>, u32> = HashMap::new();
+    /// map.insert(Rc::new("fungi".to_string()), 1);
+    ///
+    /// let shared = Rc::new("fungi".to_string());
+    /// let old = map.replace_key(Rc::clone(&shared)).unwrap();
+    /// assert_eq!(Rc::strong_count(&old), 1);
+    /// assert_eq!(Rc::strong_count(&shared), 2);
+    /// assert_eq!(map.replace_key(Rc::new("moss".to_string())), None);
+    /// ```
+    #[inline]
+    pub fn replace_key(&mut self, key: K) -> Option<K> {
+        match self.entry(key) {
+            Entry::Occupied(e) => Some(e.replace_key()),
+            Entry::Vacant(_) => None,
+        }
+    }
+
+    /// Removes a key from the map, returning the stored key and value if the
+    /// key was previously in the map.
+    ///
+    /// The key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: ../../std/cmp/trait.Eq.html
+    /// [`Hash`]: ../../std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.remove_entry(&1), Some((1, "a")));
+    /// assert_eq!(map.remove(&1), None);
+    /// ```
+    #[inline]
+    pub fn remove_entry<Q: ?Sized>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match &mut self.0 {
+            HashMapInt::Map(m) => m.remove_entry(k),
+            HashMapInt::Vec(m) => m.remove_entry(k),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Retains only the elements specified by the predicate.
+    ///
+    /// In other words, remove all pairs `(k, v)` such that `f(&k, &mut v)` returns `false`.
+    /// The elements are visited in unsorted (and unspecified) order.
+    ///
+    /// A map backed map that is left with few enough elements to fit into a
+    /// vector is moved back to the vector backend, unless it was hinted to be
+    /// large. The vector gets a clone of the hasher.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<i32, i32> = (0..8).map(|x| (x, x*10)).collect();
+    /// map.retain(|&k, _| k % 2 == 0);
+    /// assert_eq!(map.len(), 4);
+    ///
+    /// let mut map: HashMap<i32, i32> = (0..100).map(|x| (x, x*10)).collect();
+    /// assert!(map.is_map());
+    /// map.retain(|&k, _| k < 10);
+    /// assert!(map.is_vec());
+    /// ```
+    #[inline]
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+        S: Clone,
+    {
+        match &mut self.0 {
+            HashMapInt::Map(m) => {
+                m.retain(f);
+                self.maybe_downgrade();
+            }
+            HashMapInt::Vec(m) => m.retain(f),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Retains only the elements specified by the predicate like [`retain`],
+    /// also passing the position of every element to it.
+    ///
+    /// The position is the one the element had when iterating over the map
+    /// before the call, for vector backed maps that is its index in the
+    /// vector. Map backed maps have no meaningful order, their positions are
+    /// only useful to limit how many elements are kept.
+    ///
+    /// [`retain`]: #method.retain
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<&str, i32> = HashMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// map.retain_indexed(|i, _, _| i < 2);
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    #[inline]
+    pub fn retain_indexed<F>(&mut self, mut f: F)
+    where
+        F: FnMut(usize, &K, &mut V) -> bool,
+        S: Clone,
+    {
+        // both backends visit the elements once each in iteration order
+        let mut idx = 0;
+        self.retain(|k, v| {
+            let keep = f(idx, k, v);
+            idx += 1;
+            keep
+        });
+    }
+
+    /// Retains only the elements specified by the predicate like [`retain`],
+    /// also reporting if this moved a map backed map to the vector backend.
+    ///
+    /// [`retain`]: #method.retain
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<i32, i32> = (0..100).map(|x|(x, x*10)).collect();
+    /// let demoted = map.retain_reporting(|&k, _| k % 2 == 0);
+    /// assert!(!demoted);
+    /// let demoted = map.retain_reporting(|&k, _| k < 8);
+    /// assert!(demoted);
+    /// assert!(map.is_vec());
+    /// assert_eq!(map.len(), 4);
+    /// ```
+    #[inline]
+    pub fn retain_reporting<F>(&mut self, f: F) -> bool
+    where
+        F: FnMut(&K, &mut V) -> bool,
+        S: Clone,
+    {
+        let was_map = self.is_map();
+        self.retain(f);
+        was_map && self.is_vec()
+    }
+
+    /// Removes all elements `pred` returns `true` for, and returns them in
+    /// a new map using a clone of this map's hasher and tuning hints.
+    ///
+    /// This is a [`retain`] that keeps what it removes. The backend of the
+    /// returned map is picked based on the number of removed elements and
+    /// the vector limit of `self`, the backend of `self` stays the same.
+    ///
+    /// [`retain`]: #method.retain
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<i32, i32> = (0..8).map(|x| (x, x * 10)).collect();
+    /// let odd = map.filter_out(|&k, _| k % 2 == 1);
+    /// assert_eq!(map.len(), 4);
+    /// assert_eq!(odd.len(), 4);
+    /// assert_eq!(odd[&3], 30);
+    /// ```
+    #[must_use = "use `retain` if the removed elements are not needed"]
+    pub fn filter_out<F>(&mut self, mut pred: F) -> Self
+    where
+        F: FnMut(&K, &V) -> bool,
+        S: Clone,
+    {
+        let removed: Vec<(K, V)> = match &mut self.0 {
+            HashMapInt::Map(m) => m.extract_if(|k, v| pred(k, v)).collect(),
+            HashMapInt::Vec(m) => m.extract(pred),
+            HashMapInt::None => none_state!(),
+        };
+        let hash_builder = self.hasher().clone();
+        let backend = if self.1.large() || removed.len() > self.1.vec_limit() {
+            let mut m = HashBrown::with_capacity_and_hasher(removed.len(), hash_builder);
+            m.extend(removed);
+            HashMapInt::Map(m)
+        } else {
+            HashMapInt::Vec(VecMap::from_parts(removed, hash_builder))
+        };
+        Self(backend, self.1.clone())
+    }
+
+    /// Creates a new map holding the entries of both `self` and `other`,
+    /// leaving both of them untouched. For keys present in both maps the
+    /// value is the result of `f` called with the key, the value in `self`
+    /// and the value in `other`.
+    ///
+    /// The new map keeps the hasher and the tuning hints of `self`, its
+    /// backend is picked based on the size of the union.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut a = HashMap::new();
+    /// a.insert("a", 1);
+    /// a.insert("b", 2);
+    /// let mut b = HashMap::new();
+    /// b.insert("b", 3);
+    /// b.insert("c", 4);
+    ///
+    /// let u = a.union_with(&b, |_, x, y| x + y);
+    /// assert_eq!(u.len(), 3);
+    /// assert_eq!(u["a"], 1);
+    /// assert_eq!(u["b"], 5);
+    /// assert_eq!(u["c"], 4);
+    /// assert_eq!(a.len(), 2);
+    /// ```
+    #[must_use]
+    pub fn union_with<F>(&self, other: &Self, mut f: F) -> Self
+    where
+        K: Clone,
+        V: Clone,
+        F: FnMut(&K, &V, &V) -> V,
+        S: Clone,
+    {
+        let len = self.len() + other.keys().filter(|k| !self.contains_key(*k)).count();
+        let hash_builder = self.hasher().clone();
+        let mut union = if self.1.large() || len > self.1.vec_limit() {
+            HashMapInt::Map(HashBrown::with_capacity_and_hasher(len, hash_builder))
+        } else {
+            HashMapInt::Vec(VecMap::with_capacity_and_hasher(len, hash_builder))
+        };
+        let mut push = |k: K, v: V| match &mut union {
+            HashMapInt::Map(m) => {
+                m.insert(k, v);
+            }
+            HashMapInt::Vec(m) => m.insert_nocheck(k, v),
+            HashMapInt::None => none_state!(),
+        };
+        for (k, v) in self {
+            let v = match other.get(k) {
+                Some(theirs) => f(k, v, theirs),
+                None => v.clone(),
+            };
+            push(k.clone(), v);
+        }
+        for (k, v) in other {
+            if !self.contains_key(k) {
+                push(k.clone(), v.clone());
+            }
+        }
+        Self(union, self.1.clone())
+    }
+
+    /// Returns `true` if every key of this map is also a key of `other`,
+    /// the values are not compared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut a = HashMap::new();
+    /// a.insert("a", 1);
+    /// let mut b = HashMap::new();
+    /// b.insert("a", "x");
+    /// b.insert("b", "y");
+    ///
+    /// assert!(a.is_key_subset_of(&b));
+    /// assert!(!b.is_key_subset_of(&a));
+    /// ```
+    #[must_use]
+    pub fn is_key_subset_of<V2, S2>(&self, other: &HashMap<K, V2, S2>) -> bool
+    where
+        S2: BuildHasher,
+    {
+        self.len() <= other.len() && self.keys().all(|k| other.contains_key(k))
+    }
+
+    /// Returns `true` if every key of `other` is also a key of this map,
+    /// the values are not compared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut a = HashMap::new();
+    /// a.insert("a", 1);
+    /// a.insert("b", 2);
+    /// let mut b = HashMap::new();
+    /// b.insert("b", "y");
+    ///
+    /// assert!(a.is_key_superset_of(&b));
+    /// assert!(!b.is_key_superset_of(&a));
+    /// ```
+    #[must_use]
+    pub fn is_key_superset_of<V2, S2>(&self, other: &HashMap<K, V2, S2>) -> bool
+    where
+        S2: BuildHasher,
+    {
+        other.is_key_subset_of(self)
+    }
+
+    /// Creates a `HashMap` from an iterator like [`collect`] does, but
+    /// fails on the first key that is repeated instead of keeping the last
+    /// value given for it.
+    ///
+    /// [`collect`]: https://doc.rust-lang.org/std/iter/trait.Iterator.html#method.collect
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DuplicateKey`] holding the first key that is already in
+    /// the map when it is encountered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let map: HashMap<&str, i32> = HashMap::try_from_iter(vec![("a", 1), ("b", 2)]).unwrap();
+    /// assert_eq!(map.len(), 2);
+    ///
+    /// let err = HashMap::<&str, i32>::try_from_iter(vec![("a", 1), ("a", 2)]).unwrap_err();
+    /// assert_eq!(err.key, "a");
+    /// ```
+    pub fn try_from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, DuplicateKey<K>>
+    where
+        S: Default,
+    {
+        let iter = iter.into_iter();
+        let mut map = Self::presized(iter.size_hint().0, S::default());
+        for (k, v) in iter {
+            if map.contains_key(&k) {
+                return Err(DuplicateKey { key: k });
+            }
+            map.insert(k, v);
+        }
+        Ok(map)
+    }
+
+    /// Retains only the elements for which the future returned by the
+    /// predicate resolves to `true`.
+    ///
+    /// The predicate is called, and its future awaited, for one entry after
+    /// the other. All decisions are collected first and the rejected keys
+    /// are removed once all of them are made, so the predicate always sees
+    /// the map as it was when `retain_async` was called. As the map is
+    /// mutably borrowed for the whole call it can't change underneath it,
+    /// but anything the predicate checks externally may well have changed
+    /// between the decision and the removal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let mut map: HashMap<i32, i32> = (0..8).map(|x| (x, x * 10)).collect();
+    /// map.retain_async(|&k, _| async move { k % 2 == 0 }).await;
+    /// assert_eq!(map.len(), 4);
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    pub async fn retain_async<F, Fut>(&mut self, mut f: F)
+    where
+        K: Clone,
+        F: FnMut(&K, &V) -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
+        let mut rejected = Vec::new();
+        for (k, v) in self.iter() {
+            if !f(k, v).await {
+                rejected.push(k.clone());
+            }
+        }
+        for k in &rejected {
+            self.remove(k);
+        }
+    }
+
+    /// Clears the map and fills it with the key-value pairs of `iter`, like
+    /// collecting into a new map would, but reusing the memory this map
+    /// already allocated.
+    ///
+    /// The map keeps its backend if it is a map already and gets promoted
+    /// if the pairs don't fit into a vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.collect_into((0..4).map(|x| (x, x * 10)));
+    /// assert_eq!(map.len(), 4);
+    ///
+    /// map.collect_into((10..12).map(|x| (x, x * 10)));
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map[&11], 110);
+    /// ```
+    pub fn collect_into<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        S: Default,
+    {
+        self.clear();
+        let iter = iter.into_iter();
+        self.grow(iter.size_hint().0);
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+
+    /// Creates a map from the key-value pairs of `iter`, keeping the first
+    /// value of every key instead of the last one like collecting does.
+    ///
+    /// The map is vector backed as long as the pairs fit into a vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let records = [("ant", 1), ("bee", 2), ("ant", 3)];
+    /// let first: HashMap<_, _> = HashMap::from_iter_keep_first(records.iter().copied());
+    /// assert_eq!(first[&"ant"], 1);
+    /// assert_eq!(first[&"bee"], 2);
+    /// ```
+    pub fn from_iter_keep_first<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        S: Default,
+    {
+        let iter = iter.into_iter();
+        let mut map = Self::presized(iter.size_hint().0, S::default());
+        for (k, v) in iter {
+            map.insert_if_absent(k, v);
+        }
+        map
+    }
+
+    /// Creates a map from key-value pairs that are sorted by strictly
+    /// increasing keys.
+    ///
+    /// Sorted keys can't repeat, so pairs are put into the vector backend
+    /// without looking for the key first, turning the quadratic cost of
+    /// filling a vector into a linear one. Maps that don't fit into a
+    /// vector are promoted like [`insert`] would. The vector isn't kept
+    /// sorted afterwards, lookups scan it as usual.
+    ///
+    /// Whether the keys are sorted is only checked in debug builds, passing
+    /// unsorted keys in release builds can leave duplicate keys in the map.
+    ///
+    /// [`insert`]: #method.insert
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let map: HashMap<u32, &str> = HashMap::from_sorted_iter(vec![(1, "a"), (2, "b"), (5, "c")]);
+    /// assert!(map.is_vec());
+    /// assert_eq!(map[&5], "c");
+    /// ```
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Ord,
+        S: Default,
+    {
+        let mut iter = iter.into_iter().peekable();
+        let mut map = Self::presized(iter.size_hint().0, S::default());
+        while let Some((k, v)) = iter.next() {
+            debug_assert!(
+                iter.peek().map_or(true, |(next, _)| k < *next),
+                "keys passed to from_sorted_iter must be sorted and unique"
+            );
+            match &mut map.0 {
+                HashMapInt::Vec(m) if m.len() < map.1.vec_limit() => m.insert_nocheck(k, v),
+                HashMapInt::Vec(_) => {
+                    map.swap_to_map(iter.size_hint().0.saturating_add(1));
+                    map.insert_nocheck(k, v);
+                }
+                HashMapInt::Map(m) => {
+                    m.insert(k, v);
+                }
+                HashMapInt::None => none_state!(),
+            }
+        }
+        map
+    }
+
+    /// Inserts element, this ignores check in the vector
+    /// map if keys are present - it's a fast way to build
+    /// a new map when uniqueness is known ahead of time.
+    ///
+    /// If a key does get inserted twice its first value wins: that's the
+    /// one lookups return, and the one kept when the duplicates are removed
+    /// or the map is promoted.
+    #[inline]
+    pub fn insert_nocheck(&mut self, k: K, v: V) {
+        match &mut self.0 {
+            HashMapInt::Map(m) => {
+                m.insert(k, v);
+            }
+            HashMapInt::Vec(m) => {
+                m.insert_nocheck(k, v);
+                self.1.set_maybe_duplicates(true);
+            }
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Checks if the current backend is a map, if so returns
+    /// true.
+    pub fn is_map(&self) -> bool {
+        match &self.0 {
+            HashMapInt::Map(_m) => true,
+            HashMapInt::Vec(_m) => false,
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Checks if the current backend is a vector, if so returns
+    /// true.
+    pub fn is_vec(&self) -> bool {
+        match &self.0 {
+            HashMapInt::Map(_m) => false,
+            HashMapInt::Vec(_m) => true,
+            HashMapInt::None => none_state!(),
+        }
+    }
+}
+
+/// Inserts `entries` with their precomputed `hashes` into `m`, which needs
+/// to have the capacity for them already so no rehashing happens.
+fn insert_hashed<K, V, S, I>(m: &mut HashBrown<K, V, S>, entries: I, hashes: Vec<u64>)
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    I: IntoIterator<Item = (K, V)>,
+{
+    for ((k, v), hash) in entries.into_iter().zip(hashes) {
+        // vectors built with `insert_nocheck` may hold duplicates, lookups
+        // on the vector find the first of them, so that one is kept like
+        // `dedup` does.
+        if let hashbrown::hash_map::RawEntryMut::Vacant(e) =
+            m.raw_entry_mut().from_key_hashed_nocheck(hash, &k)
+        {
+            e.insert_hashed_nocheck(hash, k, v);
+        }
+    }
+}
+
+/// Error returned by [`HashMap::try_from_iter`] when a key shows up more
+/// than once.
+///
+/// [`HashMap::try_from_iter`]: struct.HashMap.html#method.try_from_iter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKey<K> {
+    /// The key that was repeated
+    pub key: K,
+}
+
+impl<K: Debug> fmt::Display for DuplicateKey<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "duplicate key: {:?}", self.key)
+    }
+}
+
+impl<K: Debug> std::error::Error for DuplicateKey<K> {}
+
+/// Prefetches the control bytes the probe for `hash` starts at, and the
+/// bucket they belong to, which is stored right before the control bytes.
+#[cfg(feature = "prefetch")]
+#[inline(always)]
+fn prefetch_hash<K, V, S>(m: &HashBrown<K, V, S>, hash: u64) {
+    let table = m.raw_table();
+    // hashbrown ignores the high bits of the hash on 32 bit targets as well
+    #[allow(clippy::cast_possible_truncation)]
+    let pos = hash as usize & (table.buckets() - 1);
+    // prefetches never fault, so the addresses are computed without
+    // dereferencing them and may point past an empty table
+    let ctrl = table.data_end().as_ptr();
+    prefetch_read(ctrl.cast::<u8>().wrapping_add(pos));
+    prefetch_read(ctrl.wrapping_sub(pos + 1));
+}
+
+/// Issues a read prefetch for the memory behind `p`.
+#[cfg(feature = "prefetch")]
+#[inline(always)]
+fn prefetch_read<T>(p: *const T) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: sse is part of the x86_64 baseline and prefetching any
+    // address is allowed, it never faults
+    unsafe {
+        use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        _mm_prefetch::<_MM_HINT_T0>(p.cast());
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = p;
+}
+
+impl<K, Q: ?Sized, V, S> Index<&Q> for HashMap<K, V, S>
+where
+    K: Eq + Hash + Borrow<Q>,
+    Q: Eq + Hash,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    /// Returns a reference to the value corresponding to the supplied key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not present in the `HashMap`.
+    #[inline]
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K, V, S, const N: usize> From<[(K, V); N]> for HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    /// Creates a map from an array of key-value pairs, vector backed if the
+    /// array fits into a vector. Like collecting, a key that appears more
+    /// than once ends up with the last value given for it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let map: HashMap<i32, &str> = HashMap::from([(1, "a"), (2, "b"), (1, "c")]);
+    /// assert!(map.is_vec());
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map[&1], "c");
+    /// ```
+    fn from(arr: [(K, V); N]) -> Self {
+        IntoIterator::into_iter(arr).collect()
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    S: BuildHasher,
+    K: Eq + Hash,
+{
+    /// Creates a raw entry builder for the `HashMap`.
+    ///
+    /// Raw entries provide the lowest level of control for searching and
+    /// manipulating a map. They must be manually initialized with a hash and
+    /// then manually searched. After this, insertions into a vacant entry
+    /// still require an owned key to be provided.
+    ///
+    /// Raw entries are useful for such exotic situations as:
+    ///
+    /// * Hash memoization
+    /// * Deferring the creation of an owned key until it is known to be required
+    /// * Using a search key that doesn't work with the Borrow trait
+    /// * Using custom comparison logic without newtype wrappers
+    ///
+    /// Because raw entries provide much more low-level control, it's much easier
+    /// to put the `HashMap` into an inconsistent state which, while memory-safe,
+    /// will cause the map to produce seemingly random results. Higher-level and
+    /// more foolproof APIs like `entry` should be preferred when possible.
+    ///
+    /// In particular, the hash used to initialized the raw entry must still be
+    /// consistent with the hash of the key that is ultimately stored in the entry.
+    /// This is because implementations of `HashMap` may need to recompute hashes
+    /// when resizing, at which point only the keys are available.
+    ///
+    /// Raw entries give mutable access to the keys. This must not be used
+    /// to modify how the key would compare or hash, as the map will not re-evaluate
+    /// where the key should go, meaning the keys may become "lost" if their
+    /// location does not reflect their state. For instance, if you change a key
+    /// so that the map now contains keys which compare equal, search may start
+    /// acting erratically, with two keys randomly masking each other. Implementations
+    /// are free to assume this doesn't happen (within the limits of memory-safety).
+    #[inline]
+    pub fn raw_entry_mut(&mut self) -> RawEntryBuilderMut<'_, K, V, S> {
+        // a full vector only gets promoted once a vacant entry is filled
+        if let HashMapInt::Vec(m) = &self.0 {
+            if m.len() >= self.1.vec_limit() {
+                return RawEntryBuilderMut::promoting(self);
+            }
+        }
+        match &mut self.0 {
+            HashMapInt::Vec(m) => RawEntryBuilderMut::from(m.raw_entry_mut()),
+            HashMapInt::Map(m) => RawEntryBuilderMut::from(m.raw_entry_mut()),
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Gets the entry for a borrowed form of the key for in-place
+    /// manipulation, the owned key is only created from it when a value
+    /// is inserted into a vacant entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut words: HashMap<String, usize> = HashMap::new();
+    ///
+    /// for word in "a short treatise on fungi and a treatise on moss".split(' ') {
+    ///     *words.entry_ref(word).or_insert(0) += 1;
+    /// }
+    ///
+    /// assert_eq!(words["treatise"], 2);
+    /// assert_eq!(words["moss"], 1);
+    /// assert_eq!(words.get("lichen"), None);
+    /// ```
+    ///
+    /// Any owned key that can be created from its borrowed form works, like
+    /// byte vectors looked up by slices:
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut lines: HashMap<Vec<u8>, usize> = HashMap::new();
+    ///
+    /// for line in b"spore\ncap\nspore".split(|b| *b == b'\n') {
+    ///     *lines.entry_ref(line).or_default() += 1;
+    /// }
+    ///
+    /// assert_eq!(lines[&b"spore"[..]], 2);
+    /// assert_eq!(lines[&b"cap"[..]], 1);
+    /// ```
+    #[inline]
+    pub fn entry_ref<'a, 'b, Q: ?Sized>(&'a mut self, key: &'b Q) -> EntryRef<'a, 'b, K, Q, V, S>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        // only the map backend cares about the hash, so we compute it once
+        // for the lookup and the insert.
+        let hash = match &self.0 {
+            HashMapInt::Map(m) => Some(m.hasher().hash_one(key)),
+            HashMapInt::Vec(_) => None,
+            HashMapInt::None => none_state!(),
+        };
+        let entry = match hash {
+            Some(hash) => self.raw_entry_mut().from_key_hashed_nocheck(hash, key),
+            None => self.raw_entry_mut().from_key(key),
+        };
+        match entry {
+            RawEntryMut::Occupied(e) => EntryRef::Occupied(e.into()),
+            RawEntryMut::Vacant(e) => EntryRef::Vacant(VacantEntryRef::new(key, hash, e)),
+        }
+    }
+
+    /// Gets the entry for the key `eq` matches, comparing keys with `eq`
+    /// instead of their `Eq` implementation. `key` is inserted if the entry
+    /// is vacant.
+    ///
+    /// Only keys that hash the same as `key` are passed to `eq`, no matter
+    /// whether the map is vector or map backed, so both backends find the
+    /// same entry. A comparator that matches keys with a different hash,
+    /// like a case insensitive one with a case sensitive hasher, won't find
+    /// them on either backend.
+    ///
+    /// # Examples
+    ///
+    /// Keys are stored in lower case, so looking them up ignoring case
+    /// with the lower cased key agrees with the hash.
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<String, u32> = HashMap::new();
+    /// map.insert("spore".to_string(), 1);
+    ///
+    /// let key = "SPORE";
+    /// *map
+    ///     .entry_by(key.to_ascii_lowercase(), |k| k.eq_ignore_ascii_case(key))
+    ///     .or_insert(0) += 1;
+    /// assert_eq!(map["spore"], 2);
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    #[inline]
+    pub fn entry_by<F>(&mut self, key: K, mut eq: F) -> Entry<'_, K, V, S>
+    where
+        F: FnMut(&K) -> bool,
+        S: Clone,
+    {
+        let hash_builder = self.hasher().clone();
+        let hash = hash_builder.hash_one(&key);
+        let matches = |k: &K| eq(k) && hash_builder.hash_one(k) == hash;
+        // Only inserting a new key into a full vector promotes it, like
+        // in `entry`.
+        if let HashMapInt::Vec(m) = &self.0 {
+            if m.len() >= self.1.vec_limit() {
+                return match m.position(matches) {
+                    Some(idx) => match &mut self.0 {
+                        HashMapInt::Vec(m) => {
+                            vecmap::Entry::Occupied(vecmap::OccupiedEntry::new(idx, key, m)).into()
+                        }
+                        _ => unreachable!(),
+                    },
+                    None => Entry::Vacant(VacantEntry::promoting(key, self)),
+                };
+            }
+        }
+        match &mut self.0 {
+            HashMapInt::Map(m) => {
+                let len = m.len();
+                let entry = m.raw_entry_mut().from_hash(hash, matches);
+                Entry::from_map(entry, key, hash, len)
+            }
+            HashMapInt::Vec(m) => match m.position(matches) {
+                Some(idx) => {
+                    vecmap::Entry::Occupied(vecmap::OccupiedEntry::new(idx, key, m)).into()
+                }
+                None => vecmap::Entry::Vacant(vecmap::VacantEntry::new(key, m)).into(),
+            },
+            HashMapInt::None => none_state!(),
+        }
+    }
+
+    /// Creates a raw immutable entry builder for the `HashMap`.
+    ///
+    /// Raw entries provide the lowest level of control for searching and
+    /// manipulating a map. They must be manually initialized with a hash and
+    /// then manually searched.
+    ///
+    /// This is useful for
+    /// * Hash memoization
+    /// * Using a search key that doesn't work with the Borrow trait
+    /// * Using custom comparison logic without newtype wrappers
+    ///
+    /// Unless you are in such a situation, higher-level and more foolproof APIs like
+    /// `get` should be preferred.
+    ///
+    /// Immutable raw entries have very limited use; you might instead want `raw_entry_mut`.
+    #[inline]
+    pub fn raw_entry(&self) -> RawEntryBuilder<'_, K, V, S> {
+        match &self.0 {
+            HashMapInt::Vec(m) => RawEntryBuilder::from(m.raw_entry()),
+            HashMapInt::Map(m) => RawEntryBuilder::from(m.raw_entry()),
+            HashMapInt::None => none_state!(),
+        }
+    }
+}
+
+impl<K, V, S, S1> PartialEq<HashMap<K, V, S1>> for HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    V: PartialEq,
+    S1: BuildHasher,
+{
+    fn eq(&self, other: &HashMap<K, V, S1>) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+
+        self.iter()
+            .all(|(key, value)| other.get(key).is_some_and(|v| *value == *v))
+    }
+}
+
+impl<K, V, S> Eq for HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    V: Eq,
+    S: BuildHasher,
+{
+}
+
+/// Hashes the map independent of its iteration order, so equal maps hash
+/// the same no matter the backend or the order entries were inserted in.
+///
+/// Every entry is hashed on its own with a fixed hasher and the results are
+/// combined with XOR, the map's own `BuildHasher` isn't involved.
+impl<K, V, S> Hash for HashMap<K, V, S>
+where
+    K: Hash,
+    V: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let combined = self.iter().fold(0, |acc, entry| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            entry.hash(&mut hasher);
+            acc ^ hasher.finish()
+        });
+        state.write_usize(self.len());
+        state.write_u64(combined);
+    }
+}
+
+//#[derive(Clone)]
+/// Iterator over the keys
+pub struct Keys<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next().map(|(k, _)| k)
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Allocates a buffer for the rest of the iterator on the first
+/// `next_back` over a map backed map, like [`Iter`] does.
+///
+/// [`Iter`]: struct.Iter.html
+impl<'a, K, V> DoubleEndedIterator for Keys<'a, K, V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<&'a K> {
+        self.inner.next_back().map(|(k, _)| k)
+    }
+}
+
+//#[derive(Clone)]
+/// Iterator over the values
+pub struct Values<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Allocates a buffer for the rest of the iterator on the first
+/// `next_back` over a map backed map, like [`Iter`] does.
+///
+/// [`Iter`]: struct.Iter.html
+impl<'a, K, V> DoubleEndedIterator for Values<'a, K, V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<&'a V> {
+        self.inner.next_back().map(|(_, v)| v)
+    }
+}
+
+//#[derive(Clone)]
+/// Mutable iterator over the values
+pub struct ValuesMut<'a, K, V> {
+    inner: IterMut<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a mut V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Allocates a buffer for the rest of the iterator on the first
+/// `next_back` over a map backed map, like [`IterMut`] does.
+///
+/// [`IterMut`]: struct.IterMut.html
+impl<'a, K, V> DoubleEndedIterator for ValuesMut<'a, K, V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<&'a mut V> {
+        self.inner.next_back().map(|(_, v)| v)
+    }
+}
+
+/// Drains the map
+pub struct Drain<'a, K, V>(DrainInt<'a, K, V>);
+
+enum DrainInt<'a, K, V> {
+    Map(hashbrown::hash_map::Drain<'a, K, V>),
+    Vec(std::vec::Drain<'a, (K, V)>),
+}
+
+impl<K, V> Iterator for Drain<'_, K, V> {
+    type Item = (K, V);
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.0 {
+            DrainInt::Map(m) => m.next(),
+            DrainInt::Vec(m) => m.next(),
+        }
+    }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.0 {
+            DrainInt::Map(m) => m.size_hint(),
+            DrainInt::Vec(m) => m.size_hint(),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn scale_up() {
+        let mut v = HashMap::new();
+        assert!(v.is_vec());
+        for i in 1..33 {
+            // 32 entries
+            v.insert(i, i);
+            assert!(v.is_vec());
+        }
+        v.insert(33, 33);
+        assert!(v.is_map());
+    }
+
+    #[test]
+    fn str_key() {
+        let mut v: HashMap<String, u32> = HashMap::new();
+        v.insert("hello".to_owned(), 42);
+        assert_eq!(v["hello"], 42);
+    }
+
+    #[test]
+    fn add_remove() {
+        let mut v = HashMap::new();
+        v.insert(1, 1);
+        v.insert(2, 2);
+        v.insert(3, 3);
+        assert_eq!(v.get(&1), Some(&1));
+        assert_eq!(v.get(&2), Some(&2));
+        assert_eq!(v.get(&3), Some(&3));
+        v.remove(&2);
+        assert_eq!(v.get(&1), Some(&1));
+        assert_eq!(v.get(&2), None);
+        assert_eq!(v.get(&3), Some(&3));
+    }
+
+    #[test]
+    fn prefetch() {
+        let mut v = HashMap::new();
+        v.prefetch(&1);
+        v.insert(1, 1);
+        v.prefetch(&1);
+        assert_eq!(v.get(&1), Some(&1));
+        for i in 2..100 {
+            v.insert(i, i);
+        }
+        assert!(v.is_map());
+        v.prefetch(&42);
+        v.prefetch(&1000);
+        assert_eq!(v.get(&42), Some(&42));
+        assert_eq!(v.get(&1000), None);
+    }
+
+    #[test]
+    fn get_batched() {
+        let mut v = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER as u64 {
+            v.insert(i, i);
+        }
+        let keys: Vec<u64> = (0..64).rev().collect();
+        let refs: Vec<&u64> = keys.iter().collect();
+        let expected: Vec<Option<&u64>> = keys
+            .iter()
+            .map(|k| {
+                if *k < VEC_LIMIT_UPPER as u64 {
+                    Some(k)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        assert!(v.is_vec());
+        assert_eq!(v.get_batched(&refs), expected);
+        v.hint_large();
+        assert_eq!(v.get_batched(&refs), expected);
+    }
+
+    #[test]
+    fn try_from_iter() {
+        let v: HashMap<u32, u32> =
+            HashMap::try_from_iter((0..40).map(|i| (i, i))).expect("no duplicates");
+        assert_eq!(v.len(), 40);
+        assert!(v.is_map());
+        let v: HashMap<u32, u32> =
+            HashMap::try_from_iter((0..4).map(|i| (i, i))).expect("no duplicates");
+        assert!(v.is_vec());
+        let e = HashMap::<u32, u32>::try_from_iter((0..40).chain(7..9).map(|i| (i, i)));
+        assert_eq!(e, Err(DuplicateKey { key: 7 }));
+        let e = HashMap::<String, u32>::try_from_iter(vec![
+            ("a".to_string(), 1),
+            ("b".to_string(), 2),
+            ("a".to_string(), 3),
+        ]);
+        assert_eq!(
+            e,
+            Err(DuplicateKey {
+                key: "a".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn map_values_in_place() {
+        let mut v: HashMap<u32, String> = HashMap::new();
+        for i in 0..10 {
+            v.insert(i, i.to_string());
+        }
+        v.map_values_in_place(|k, v| format!("{k}:{v}"));
+        assert!(v.is_vec());
+        assert_eq!(v[&3], "3:3");
+        for i in 10..40 {
+            v.insert(i, i.to_string());
+        }
+        assert!(v.is_map());
+        v.map_values_in_place(|_, v| v + "!");
+        assert_eq!(v[&3], "3:3!");
+        assert_eq!(v[&39], "39!");
+
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            v.map_values_in_place(|k, v| if *k == 3 { panic!("boom") } else { v });
+        }));
+        assert!(r.is_err());
+        assert_eq!(v.len(), 40);
+        assert_eq!(v[&3], "");
+        assert_eq!(v[&39], "39!");
+    }
+
+    #[test]
+    fn retain() {
+        let mut v: HashMap<usize, usize> = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(i, i);
+        }
+        let capacity = v.capacity();
+        let mut calls = 0;
+        v.retain(|k, v| {
+            calls += 1;
+            *v += 1;
+            k % 2 == 0
+        });
+        assert!(v.is_vec());
+        assert_eq!(calls, VEC_LIMIT_UPPER);
+        assert_eq!(v.len(), VEC_LIMIT_UPPER / 2);
+        assert_eq!(v.capacity(), capacity);
+        for i in 0..VEC_LIMIT_UPPER {
+            assert_eq!(
+                v.get(&i).copied(),
+                if i % 2 == 0 { Some(i + 1) } else { None }
+            );
+        }
+    }
+
+    std::thread_local! {
+        static PANIC_ON_HASH: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    }
+
+    /// A key that can be told to panic when hashed or dropped
+    #[derive(Debug)]
+    struct Touchy(usize, bool);
+
+    impl PartialEq for Touchy {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+
+    impl Eq for Touchy {}
+
+    impl Hash for Touchy {
+        fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+            assert!(!PANIC_ON_HASH.with(std::cell::Cell::get), "hash panic");
+            self.0.hash(state);
+        }
+    }
+
+    impl Drop for Touchy {
+        fn drop(&mut self) {
+            assert!(!self.1 || std::thread::panicking(), "drop panic");
+        }
+    }
+
+    #[test]
+    fn promotion_unwind() {
+        let mut v = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(Touchy(i, false), i);
+        }
+
+        // the hash of the vector keys panics, nothing has moved yet
+        PANIC_ON_HASH.with(|p| p.set(true));
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            v.insert(Touchy(VEC_LIMIT_UPPER, false), 0);
+        }));
+        PANIC_ON_HASH.with(|p| p.set(false));
+        assert!(r.is_err());
+        assert!(v.is_vec());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER);
+
+        // the duplicate key gets dropped once we're already map backed
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            v.insert(Touchy(0, true), 42);
+        }));
+        assert!(r.is_err());
+        assert!(v.is_map());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER);
+        for i in 0..VEC_LIMIT_UPPER {
+            assert_eq!(
+                v.get(&Touchy(i, false)),
+                Some(if i == 0 { &42 } else { &i })
+            );
+        }
+        v.insert(Touchy(VEC_LIMIT_UPPER, false), 0);
+        assert_eq!(v.len(), VEC_LIMIT_UPPER + 1);
+    }
+
+    #[test]
+    fn entry_promotion_unwind() {
+        let mut v = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(Touchy(i, false), i);
+        }
+
+        // the value closure panics before anything is inserted
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            v.entry(Touchy(VEC_LIMIT_UPPER, false))
+                .or_insert_with(|| panic!("value panic"));
+        }));
+        assert!(r.is_err());
+        assert!(v.is_vec());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER);
+
+        // hashing panics while promoting, through entries and raw entries
+        PANIC_ON_HASH.with(|p| p.set(true));
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            v.entry(Touchy(VEC_LIMIT_UPPER, false)).or_insert(0);
+        }));
+        assert!(r.is_err());
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            v.raw_entry_mut()
+                .from_key(&Touchy(VEC_LIMIT_UPPER, false))
+                .or_insert(Touchy(VEC_LIMIT_UPPER, false), 0);
+        }));
+        assert!(r.is_err());
+        PANIC_ON_HASH.with(|p| p.set(false));
+        assert!(v.is_vec());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER);
+        assert!((0..VEC_LIMIT_UPPER).all(|i| v.get(&Touchy(i, false)) == Some(&i)));
+
+        *v.entry(Touchy(VEC_LIMIT_UPPER, false)).or_insert(0) += 1;
+        assert!(v.is_map());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER + 1);
+        assert_eq!(v.get(&Touchy(VEC_LIMIT_UPPER, false)), Some(&1));
+    }
+
+    #[test]
+    fn promotion_unwind_paths() {
+        let promote: [fn(&mut HashMap<Touchy, usize>); 4] = [
+            |v| v.reserve(VEC_LIMIT_UPPER),
+            |v| {
+                let _ = v.checked_insert(Touchy(VEC_LIMIT_UPPER, false), 0);
+            },
+            |v| v.extend(std::iter::once((Touchy(VEC_LIMIT_UPPER, false), 0))),
+            |v| {
+                v.insert_nocheck(Touchy(VEC_LIMIT_UPPER, false), 0);
+                v.compact();
+            },
+        ];
+        for f in promote {
+            let mut v = HashMap::new();
+            for i in 0..VEC_LIMIT_UPPER {
+                v.insert(Touchy(i, false), i);
+            }
+            PANIC_ON_HASH.with(|p| p.set(true));
+            let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(&mut v)));
+            PANIC_ON_HASH.with(|p| p.set(false));
+            assert!(r.is_err());
+            assert!(v.is_vec());
+            assert!((0..VEC_LIMIT_UPPER).all(|i| v.get(&Touchy(i, false)) == Some(&i)));
+
+            // the map is still usable and promotes once hashing works
+            f(&mut v);
+            assert!(v.is_map());
+            assert!((0..VEC_LIMIT_UPPER).all(|i| v.get(&Touchy(i, false)) == Some(&i)));
+        }
+    }
+
+    #[test]
+    fn union_with() {
+        let a: HashMap<u32, u32> = (0..20).map(|i| (i, i)).collect();
+        let b: HashMap<u32, u32> = (10..30).map(|i| (i, i)).collect();
+        let u = a.union_with(&b, |_, x, y| x + y);
+        assert!(u.is_vec());
+        assert_eq!(u.len(), 30);
+        for i in 0..30 {
+            assert_eq!(u[&i], if (10..20).contains(&i) { 2 * i } else { i });
+        }
+        let c: HashMap<u32, u32> = (20..40).map(|i| (i, i)).collect();
+        let u = u.union_with(&c, |_, x, y| x * y);
+        assert!(u.is_map());
+        assert_eq!(u.len(), 40);
+        assert_eq!(u[&15], 30);
+        assert_eq!(u[&25], 625);
+        assert_eq!(u[&35], 35);
+        assert_eq!(a.len(), 20);
+        assert_eq!(b.len(), 20);
+
+        // sized by the union, not by `self`
+        let big: HashMap<u32, u32> = (0..40).map(|i| (i, i)).collect();
+        let small: HashMap<u32, u32> = (0..4).map(|i| (i, i)).collect();
+        assert!(small.union_with(&big, |_, x, _| *x).is_map());
+        assert!(big.union_with(&small, |_, x, _| *x).is_map());
+
+        // respects the vector limit of `self`
+        let mut small = HashMap::with_max_vec_capacity(4);
+        small.insert(0, 0);
+        let u = small.union_with(&a, |_, x, _| *x);
+        assert!(u.is_map());
+        assert_eq!(u.max_vec_capacity(), 4);
+        let mut small = HashMap::with_max_vec_capacity(64);
+        small.insert(0, 0);
+        assert!(small.union_with(&u, |_, x, _| *x).is_vec());
+
+        // keeps the hasher of `self`
+        let mut seeded = HashMap::with_hasher(std::collections::hash_map::RandomState::new());
+        seeded.extend((0..40).map(|i| (i, i)));
+        let u = seeded.union_with(&seeded.clone(), |_, x, y| x + y);
+        assert!(u.is_map());
+        assert!((0..40).all(|i| u.lookup(seeded.hashed_key(&i)) == Some(&(2 * i))));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn retain_async() {
+        let mut v: HashMap<u32, u32> = HashMap::new();
+        for i in 0..10 {
+            v.insert(i, i * 10);
+        }
+        let mut seen = 0;
+        v.retain_async(|k, v| {
+            seen += 1;
+            let keep = *v == k * 10 && k % 3 == 0;
+            async move {
+                tokio::task::yield_now().await;
+                keep
+            }
+        })
+        .await;
+        assert_eq!(seen, 10);
+        assert!(v.is_vec());
+        let mut keys: Vec<u32> = v.keys().copied().collect();
+        keys.sort_unstable();
+        assert_eq!(keys, vec![0, 3, 6, 9]);
+
+        v.hint_large();
+        v.retain_async(|k, _| std::future::ready(*k > 3)).await;
+        assert!(v.is_map());
+        assert_eq!(v.len(), 2);
+    }
+
+    #[test]
+    fn iter_copied() {
+        let mut v: HashMap<u32, u32> = HashMap::new();
+        for i in 0..32 {
+            v.insert(i, i * 2);
+        }
+        assert!(v.is_vec());
+        assert_eq!(v.iter_copied().map(|(_, v)| v).sum::<u32>(), 992);
+        v.hint_large();
+        assert_eq!(v.iter_copied().map(|(k, v)| v - k).sum::<u32>(), 496);
+        assert_eq!(v.iter_copied().count(), VEC_LIMIT_UPPER);
+    }
+
+    #[test]
+    fn drain_sorted() {
+        let mut v = HashMap::new();
+        for i in (0..10).rev() {
+            v.insert(i, i);
+        }
+        let drained = v.drain_sorted();
+        assert_eq!(drained.len(), 10);
+        assert!(drained.eq((0..10).map(|i| (i, i))));
+        assert!(v.is_empty());
+        assert!(v.is_vec());
+
+        for i in (0..100).rev() {
+            v.insert(i, i);
+        }
+        let keys: Vec<u32> = v.drain_sorted().map(|(k, _)| k).collect();
+        assert_eq!(keys, (0..100).collect::<Vec<u32>>());
+        assert!(v.is_empty());
+        assert!(v.is_map());
+    }
+
+    #[test]
+    fn with_min_vec_capacity() {
+        let mut v = HashMap::with_min_vec_capacity(8);
+        let capacity = v.capacity();
+        assert!(capacity >= 8);
+        for i in 0..8 {
+            v.insert(i, i);
+        }
+        assert_eq!(v.capacity(), capacity);
+        assert!(v.is_vec());
+
+        let v: HashMap<u8, u8> = HashMap::with_min_vec_capacity(1000);
+        assert!(v.is_vec());
+        assert_eq!(v.capacity(), VEC_LIMIT_UPPER);
+        let v: HashMap<u8, u8> = HashMap::new();
+        assert_eq!(v.capacity(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "map observed in transient None state")]
+    fn none_state() {
+        // The `None` state only exists while the backend is swapped and
+        // transitions are written so that even a panic half way through
+        // can't leave a map in it (see `promotion_unwind`). Should it ever
+        // be observed we want a panic that points at the broken invariant.
+        let v: HashMap<u8, u8> = HashMap::from_backend(HashMapInt::None);
+        let _ = v.len();
+    }
+
+    #[test]
+    #[should_panic(expected = "map observed in transient None state")]
+    fn none_state_capacity() {
+        let v: HashMap<u8, u8> = HashMap::from_backend(HashMapInt::None);
+        let _ = v.capacity();
+    }
+
+    #[test]
+    fn and_remove_if() {
+        fn decrement(v: &mut u32) -> bool {
+            *v -= 1;
+            *v == 0
+        }
+        let mut v: HashMap<usize, u32> = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(i, 2);
+        }
+        for large in [false, true] {
+            if large {
+                v.hint_large();
+            }
+            assert!(matches!(
+                v.entry(1).and_remove_if(decrement),
+                Entry::Occupied(_)
+            ));
+            assert_eq!(v.get(&1), Some(&1));
+            match v.entry(1).and_remove_if(decrement) {
+                Entry::Vacant(e) => assert_eq!(e.key(), &1),
+                Entry::Occupied(_) => unreachable!(),
+            }
+            assert_eq!(v.get(&1), None);
+            assert!(matches!(
+                v.entry(1).and_remove_if(decrement),
+                Entry::Vacant(_)
+            ));
+            *v.entry(1).and_remove_if(decrement).or_insert(1) += 1;
+            assert_eq!(v.get(&1), Some(&2));
+            assert_eq!(v.len(), VEC_LIMIT_UPPER);
+            assert_eq!(v.is_map(), large);
+        }
+    }
+
+    #[test]
+    fn effective_capacity() {
+        let mut v = HashMap::with_capacity(VEC_LIMIT_UPPER);
+        assert_eq!(v.effective_capacity(), v.capacity());
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(i, i);
+            assert!(v.effective_capacity() >= v.len());
+            assert!(v.effective_capacity() <= VEC_LIMIT_UPPER);
+        }
+        assert_eq!(v.effective_capacity(), VEC_LIMIT_UPPER);
+        v.insert(VEC_LIMIT_UPPER, VEC_LIMIT_UPPER);
+        assert!(v.is_map());
+        assert_eq!(v.effective_capacity(), v.capacity());
+        assert!(v.effective_capacity() > VEC_LIMIT_UPPER);
+
+        let v: HashMap<u8, u8> = HashMap::vec_with_capacity(2 * VEC_LIMIT_UPPER);
+        assert!(v.capacity() >= 2 * VEC_LIMIT_UPPER);
+        assert_eq!(v.effective_capacity(), VEC_LIMIT_UPPER);
+    }
+
+    #[test]
+    fn get_many_key_value_mut() {
+        let mut v: HashMap<String, usize> = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(i.to_string(), i);
+        }
+        for large in [false, true] {
+            if large {
+                v.hint_large();
+            }
+            let mut log = Vec::new();
+            let entries = v
+                .get_many_key_value_mut(["1", "12", "3"])
+                .expect("all keys are present");
+            for (k, v) in entries {
+                log.push(k.clone());
+                *v += 100;
+            }
+            assert_eq!(log, vec!["1", "12", "3"]);
+            assert_eq!(v["12"], if large { 212 } else { 112 });
+            assert!(v.get_many_key_value_mut(["1", "12", "1"]).is_none());
+            assert!(v.get_many_key_value_mut(["1", "100"]).is_none());
+            assert!(v.get_many_key_value_mut::<str, 0>([]).is_some());
+            assert_eq!(v.is_map(), large);
+        }
+    }
+
+    #[test]
+    fn entry_promotion() {
+        let mut v = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            *v.entry(i).or_insert(0) += i;
+        }
+        assert!(v.is_vec());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER);
+
+        // occupied entries on a full vector never promote
+        for i in 0..VEC_LIMIT_UPPER {
+            *v.entry(i).or_insert(0) += 1;
+            v.entry(i).and_modify(|v| *v += 1).or_insert(0);
+            assert!(matches!(v.entry(i), Entry::Occupied(_)));
+        }
+        assert!(v.is_vec());
+        assert_eq!(v[&3], 5);
+
+        // neither does acquiring a vacant entry without inserting
+        match v.entry(VEC_LIMIT_UPPER) {
+            Entry::Vacant(e) => assert_eq!(e.into_key(), VEC_LIMIT_UPPER),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert!(v.is_vec());
+
+        // inserting into it does
+        assert_eq!(*v.entry(VEC_LIMIT_UPPER).or_insert(7), 7);
+        assert!(v.is_map());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER + 1);
+        assert_eq!(v[&3], 5);
+
+        let mut v = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(i, i);
+        }
+        assert!(v.is_vec());
+        if let Entry::Vacant(e) = v.entry(100) {
+            let o = e.insert_entry(100);
+            assert_eq!(o.get(), &100);
+        }
+        assert!(v.is_map());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER + 1);
+    }
+
+    #[test]
+    fn extend_from_slice() {
+        let pairs: Vec<(usize, usize)> = (0..40).map(|i| (i, i * 2)).collect();
+        let mut v = HashMap::new();
+        v.extend_from_slice(&pairs[..8]);
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 8);
+
+        v.extend_from_slice(&pairs);
+        assert!(v.is_map());
+        assert_eq!(v.len(), 40);
+        for (k, val) in &pairs {
+            assert_eq!(v.get(k), Some(val));
+        }
+
+        // updates to present keys only never promote the map
+        let mut v: HashMap<usize, usize> = (0..20).map(|i| (i, i)).collect();
+        v.extend_from_slice(&pairs[..20]);
+        assert!(v.is_vec());
+        assert_eq!((v.len(), v[&19]), (20, 38));
+
+        // the vec backend dedups too
+        let mut v = HashMap::new();
+        v.extend_from_slice(&[(1, 1), (2, 2), (1, 3)]);
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 2);
+        assert_eq!(v[&1], 3);
+    }
+
+    #[test]
+    fn occupied_replace_with() {
+        for n in [4, 64] {
+            let mut v: HashMap<usize, String> = HashMap::new();
+            for i in 0..n {
+                v.insert(i, i.to_string());
+            }
+            if let Entry::Occupied(o) = v.entry(3) {
+                o.replace_with(|mut s| {
+                    s.push('!');
+                    s
+                })
+                .push('?');
+            }
+            assert_eq!(v[&3], "3!?");
+            assert_eq!(v.len(), n);
+
+            let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                if let Entry::Occupied(o) = v.entry(3) {
+                    o.replace_with(|_| panic!("boom"));
+                }
+            }));
+            assert!(r.is_err());
+            assert_eq!(v.len(), n - 1);
+            assert_eq!(v.get(&3), None);
+            assert!((0..n).filter(|i| *i != 3).all(|i| v[&i] == i.to_string()));
+        }
+    }
+
+    #[test]
+    fn constructor_backends() {
+        let h = DefaultHashBuilder::default;
+        assert!(HashMap::<u8, u8>::new().is_vec());
+        assert!(HashMap::<u8, u8>::default().is_vec());
+        assert!(HashMap::<u8, u8>::with_capacity(0).is_vec());
+        assert!(HashMap::<u8, u8>::with_capacity(VEC_LIMIT_UPPER).is_vec());
+        assert!(HashMap::<u8, u8>::with_capacity(VEC_LIMIT_UPPER + 1).is_map());
+        assert!(HashMap::<u8, u8>::with_min_vec_capacity(1024).is_vec());
+        assert!(HashMap::<u8, u8>::vec_with_capacity(1024).is_vec());
+        assert!(HashMap::<u8, u8>::vec_with_hasher(h()).is_vec());
+        assert!(HashMap::<u8, u8>::with_hasher(h()).is_map());
+        assert!(HashMap::<u8, u8>::with_capacity_and_hasher(0, h()).is_map());
+        assert!(HashMap::<u8, u8>::with_capacity_and_hasher(1024, h()).is_map());
+        // collecting picks the backend from the size hint
+        assert!(std::iter::empty::<(u8, u8)>()
+            .collect::<HashMap<_, _>>()
+            .is_vec());
+        assert!((0..=VEC_LIMIT_UPPER)
+            .map(|i| (i, i))
+            .collect::<HashMap<_, _>>()
+            .is_map());
+    }
+
+    #[test]
+    fn vacant_insert_key_value() {
+        for n in [0, 8, VEC_LIMIT_UPPER, 64] {
+            let mut v: HashMap<String, usize> = HashMap::new();
+            for i in 0..n {
+                v.insert(i.to_string(), i);
+            }
+            let mut index = Vec::new();
+            if let Entry::Vacant(e) = v.entry("key".to_string()) {
+                let (k, v) = e.insert_key_value(1);
+                *v += k.len();
+                index.push(k);
+            }
+            assert_eq!(index, ["key"]);
+            assert_eq!(v["key"], 4);
+            assert_eq!(v.len(), n + 1);
+        }
+    }
+
+    #[test]
+    fn value_reductions() {
+        let mut v: HashMap<u64, u64> = HashMap::new();
+        assert_eq!(v.sum_values(), 0);
+        assert_eq!(v.product_values(), 1);
+        for i in 1..=10 {
+            v.insert(i, i);
+        }
+        assert!(v.is_vec());
+        assert_eq!(v.sum_values(), 55);
+        assert_eq!(v.product_values(), 3_628_800);
+        assert_eq!(v.fold_values(0, |acc, x| acc + x * x), 385);
+        v.hint_large();
+        assert_eq!(v.sum_values(), 55);
+        assert_eq!(v.product_values(), 3_628_800);
+        assert_eq!(v.fold_values(0, |acc, x| acc + x * x), 385);
+    }
+
+    #[test]
+    fn key_subsets() {
+        let small: HashMap<usize, ()> = HashMap::new();
+        let mut large: HashMap<usize, &str> = HashMap::new();
+        assert!(small.is_key_subset_of(&large));
+        assert!(small.is_key_superset_of(&large));
+
+        for i in 0..64 {
+            large.insert(i, "x");
+        }
+        let mut same: HashMap<usize, u8> = HashMap::new();
+        let mut half = HashMap::new();
+        for i in 0..64 {
+            same.insert(i, 0);
+            if i % 2 == 0 {
+                half.insert(i, ());
+            }
+        }
+        assert!(large.is_map());
+        assert!(half.is_vec());
+
+        assert!(same.is_key_subset_of(&large));
+        assert!(same.is_key_superset_of(&large));
+        assert!(large.is_key_subset_of(&same));
+
+        assert!(half.is_key_subset_of(&large));
+        assert!(!half.is_key_superset_of(&large));
+        assert!(large.is_key_superset_of(&half));
+        assert!(!large.is_key_subset_of(&half));
+
+        half.insert(100, ());
+        assert!(!half.is_key_subset_of(&large));
+        assert!(!large.is_key_superset_of(&half));
+        assert!(small.is_key_subset_of(&half));
+    }
+
+    #[test]
+    fn value_counts() {
+        let mut v = HashMap::new();
+        for i in 0..100_usize {
+            v.insert(i, i % 3);
+        }
+        let counts = v.value_counts();
+        assert!(counts.is_vec());
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts[&0], 34);
+        assert_eq!(counts[&1], 33);
+        assert_eq!(counts[&2], 33);
+
+        let distinct = v.iter().map(|(k, _)| (*k, *k)).collect::<HashMap<_, _>>();
+        let counts = distinct.value_counts();
+        assert!(counts.is_map());
+        assert_eq!(counts.len(), 100);
+        assert!(counts.values().all(|c| *c == 1));
+    }
+
+    #[test]
+    fn or_insert_with_unwind() {
+        // a small vector, a full one that would promote, and a map
+        for n in [4, VEC_LIMIT_UPPER, 64] {
+            let mut v: HashMap<usize, String> = HashMap::new();
+            for i in 0..n {
+                v.insert(i, i.to_string());
+            }
+            let was_vec = v.is_vec();
+            let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                v.entry(1000).or_insert_with(|| panic!("no value"));
+            }));
+            assert!(r.is_err());
+            assert_eq!(v.is_vec(), was_vec);
+            assert_eq!(v.len(), n);
+            assert!(!v.contains_key(&1000));
+            for i in 0..n {
+                assert_eq!(v[&i], i.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn get_cloned_or_default() {
+        let mut v: HashMap<String, Vec<u8>> = HashMap::new();
+        v.insert("a".to_string(), vec![1, 2]);
+        assert_eq!(v.get_cloned_or_default("a"), vec![1, 2]);
+        assert_eq!(v.get_cloned_or_default("b"), Vec::<u8>::new());
+        assert_eq!(v.len(), 1);
+        v.hint_large();
+        assert_eq!(v.get_cloned_or_default("a"), vec![1, 2]);
+        assert_eq!(v.get_cloned_or_default("b"), Vec::<u8>::new());
+        assert_eq!(v.len(), 1);
+    }
+
+    #[test]
+    fn filter_out() {
+        for (n, m) in [(16, 2), (16, 20), (100, 2), (100, 4), (100, 200)] {
+            let mut v: HashMap<usize, usize> = HashMap::new();
+            for i in 0..n {
+                v.insert(i, i * 10);
+            }
+            let was_vec = v.is_vec();
+            let removed = v.filter_out(|k, _| k % m == 0);
+            assert_eq!(v.is_vec(), was_vec);
+            assert_eq!(removed.is_map(), removed.len() > VEC_LIMIT_UPPER);
+            assert_eq!(v.len() + removed.len(), n);
+            for i in 0..n {
+                let (kept, gone) = (v.get(&i), removed.get(&i));
+                assert!(kept.is_some() != gone.is_some());
+                assert_eq!(kept.or(gone), Some(&(i * 10)));
+                assert_eq!(gone.is_some(), i % m == 0);
+            }
+        }
+
+        // the returned map uses the vector limit of the source map
+        let mut v: HashMap<usize, usize> = HashMap::with_max_vec_capacity(4);
+        v.extend((0..60).map(|i| (i, i)));
+        let removed = v.filter_out(|k, _| *k < 30);
+        assert!(removed.is_map());
+        assert_eq!(removed.max_vec_capacity(), 4);
+        let few = v.filter_out(|k, _| *k < 33);
+        assert!(few.is_vec());
+        assert_eq!(few.len(), 3);
+    }
+
+    #[test]
+    fn hint_additional() {
+        let mut v = HashMap::new();
+        v.hint_additional(100);
+        for i in 0..VEC_LIMIT_UPPER {
+            v.entry(i).or_insert(i);
+        }
+        assert!(v.is_vec());
+        v.entry(VEC_LIMIT_UPPER).or_insert(0);
+        assert!(v.is_map());
+        let capacity = v.capacity();
+        assert!(capacity >= VEC_LIMIT_UPPER + 100);
+        for i in VEC_LIMIT_UPPER..VEC_LIMIT_UPPER + 100 {
+            v.entry(i).or_insert(i);
+        }
+        assert_eq!(v.capacity(), capacity);
+
+        // without the hint the map grows while filling up
+        let mut v = HashMap::new();
+        for i in 0..=VEC_LIMIT_UPPER {
+            v.entry(i).or_insert(i);
+        }
+        assert!(v.capacity() < VEC_LIMIT_UPPER + 100);
+    }
+
+    #[test]
+    fn clone_as_vec() {
+        let mut v: HashMap<usize, String> = HashMap::new();
+        for i in 0..64 {
+            v.insert(i, i.to_string());
+        }
+        assert!(v.clone_as_vec().is_map());
+        for i in (0..64).filter(|k| k % 4 != 0) {
+            v.remove(&i);
+        }
+        assert!(v.is_map());
+        assert_eq!(v.len(), 16);
+
+        let c = v.clone_as_vec();
+        assert!(c.is_vec());
+        assert_eq!(c, v);
+        assert!(c.clone_as_vec().is_vec());
+    }
+
+    #[test]
+    fn clone_optimized() {
+        let mut v: HashMap<usize, String> = HashMap::new();
+        for i in 0..64 {
+            v.insert(i, i.to_string());
+        }
+        assert!(v.clone_optimized().is_map());
+        for i in 8..64 {
+            v.remove(&i);
+        }
+        assert!(v.is_map());
+
+        let c = v.clone_optimized();
+        assert!(c.is_vec());
+        assert_eq!(c, v);
+        assert!(c.clone_optimized().is_vec());
+
+        v.hint_large();
+        let c = v.clone_optimized();
+        assert!(c.is_map());
+        assert_eq!(c, v);
+        assert!(v.clone_as_vec().is_vec());
+    }
+
+    #[test]
+    fn keys_with_prefix() {
+        let mut v: HashMap<String, usize> = HashMap::new();
+        for i in 0..100 {
+            v.insert(format!("k{i}"), i);
+            if i == 10 {
+                assert!(v.is_vec());
+                assert_eq!(v.keys_with_prefix("k1").count(), 2);
+            }
+        }
+        assert!(v.is_map());
+        let mut found: Vec<usize> = v.keys_with_prefix("k1").map(|(_, v)| *v).collect();
+        found.sort_unstable();
+        assert_eq!(found, [1, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19]);
+        assert_eq!(v.keys_with_prefix("").count(), 100);
+        assert_eq!(v.keys_with_prefix("x").count(), 0);
+    }
+
+    #[test]
+    fn and_modify_with_key() {
+        for n in [8, 64] {
+            let mut v: HashMap<usize, usize> = HashMap::new();
+            for i in 0..n {
+                v.insert(i, 1);
+            }
+            for i in 0..=n {
+                v.entry(i).and_modify_with_key(|k, v| *v += k).or_insert(0);
+            }
+            for i in 0..n {
+                assert_eq!(v[&i], i + 1);
+            }
+            assert_eq!(v[&n], 0);
+        }
+    }
+
+    #[test]
+    fn backend_refs() {
+        let mut v = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(i, i * 2);
+        }
+        assert!(v.as_hashbrown().is_none());
+        let pairs = v.as_vec_pairs().expect("vector backed");
+        assert_eq!(pairs.len(), VEC_LIMIT_UPPER);
+        assert!(pairs.iter().all(|(k, v)| *v == k * 2));
+
+        v.insert(VEC_LIMIT_UPPER, 0);
+        assert!(v.as_vec_pairs().is_none());
+        let m = v.as_hashbrown().expect("map backed");
+        assert_eq!(m.len(), VEC_LIMIT_UPPER + 1);
+        assert_eq!(m.get(&3), Some(&6));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_shrinks() {
+        use ::serde::de::value::{Error, MapDeserializer};
+        use ::serde::Deserialize;
+
+        /// Claims to hold far more pairs than it does
+        struct OverHinted(std::ops::Range<u32>);
+        impl Iterator for OverHinted {
+            type Item = (u32, u32);
+            fn next(&mut self) -> Option<Self::Item> {
+                self.0.next().map(|i| (i, i))
+            }
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (1000, Some(1000))
+            }
+        }
+
+        for n in [4, 40, 900] {
+            let d = MapDeserializer::<_, Error>::new(OverHinted(0..n));
+            let v: HashMap<u32, u32> = HashMap::deserialize(d).expect("valid map");
+            assert_eq!(v.len(), n as usize);
+            assert!(v.capacity() <= (v.len() * 2).max(16));
+        }
+    }
+
+    #[test]
+    fn insert_if_absent() {
+        let mut v = HashMap::new();
+        for i in 0..64 {
+            assert!(v.insert_if_absent(i, i));
+            if i == VEC_LIMIT_UPPER - 1 {
+                assert!(v.is_vec());
+                assert!(!v.insert_if_absent(0, 1));
+                assert_eq!(v[&0], 0);
+            }
+        }
+        assert!(v.is_map());
+        for i in 0..64 {
+            assert!(!v.insert_if_absent(i, 0));
+            assert_eq!(v[&i], i);
+        }
+        assert_eq!(v.len(), 64);
+    }
+
+    #[test]
+    fn sorted_iter_by_total_cmp() {
+        #[derive(Debug, Clone, Copy)]
+        struct Key(f64);
+        impl PartialEq for Key {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.to_bits() == other.0.to_bits()
+            }
+        }
+        impl Eq for Key {}
+        impl Hash for Key {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.0.to_bits().hash(state);
+            }
+        }
+        impl Borrow<f64> for Key {
+            fn borrow(&self) -> &f64 {
+                &self.0
+            }
+        }
+
+        let keys = [
+            f64::NAN,
+            0.0,
+            -f64::NAN,
+            f64::INFINITY,
+            -0.0,
+            1.5,
+            f64::NEG_INFINITY,
+            -1.5,
+        ];
+        let mut v = HashMap::new();
+        for (i, k) in keys.iter().enumerate() {
+            v.insert(Key(*k), i);
+        }
+        for large in [false, true] {
+            if large {
+                v.hint_large();
+            }
+            let sorted: Vec<usize> = v.sorted_iter_by_total_cmp().map(|(_, i)| *i).collect();
+            // -NaN, -inf, -1.5, -0.0, 0.0, 1.5, inf, NaN
+            assert_eq!(sorted, [2, 6, 7, 4, 1, 5, 3, 0]);
+        }
+    }
+
+    #[test]
+    fn try_with_capacity() {
+        let v = HashMap::<usize, usize>::try_with_capacity(VEC_LIMIT_UPPER).expect("fits");
+        assert!(v.is_vec());
+        assert!(v.capacity() >= VEC_LIMIT_UPPER);
+        let v = HashMap::<usize, usize>::try_with_capacity(100).expect("fits");
+        assert!(v.is_map());
+        assert!(v.capacity() >= 100);
+
+        for huge in [usize::MAX, usize::MAX / 2] {
+            assert_eq!(
+                HashMap::<usize, usize>::try_with_capacity(huge).map(|_| ()),
+                Err(TryReserveError::CapacityOverflow)
+            );
+        }
+    }
+
+    #[test]
+    fn occupied_key_value() {
+        use std::rc::Rc;
+        for n in [4, 64] {
+            let mut v: HashMap<Rc<str>, usize> = HashMap::new();
+            let stored: Rc<str> = Rc::from("key");
+            v.insert(stored.clone(), 7);
+            for i in 0..n {
+                v.insert(Rc::from(i.to_string()), i);
+            }
+            // the stored key is returned, not the one used for the lookup
+            if let Entry::Occupied(o) = v.entry(Rc::from("key")) {
+                let (k, v) = o.key_value();
+                assert!(Rc::ptr_eq(k, &stored));
+                assert_eq!(v, &7);
+            } else {
+                unreachable!();
+            }
+        }
+    }
+
+    #[test]
+    fn interned_keys() {
+        use std::sync::Arc;
+        let mut interner = Interner::new();
+        let mut a = HashMap::new();
+        let mut b = HashMap::new();
+        for i in 0..64 {
+            let key = format!("key{i}");
+            assert_eq!(a.insert_interned(&mut interner, &key, i), None);
+            if i % 2 == 0 {
+                assert_eq!(b.insert_interned(&mut interner, &key, i * 2), None);
+            }
+        }
+        assert_eq!(a.insert_interned(&mut interner, "key0", 100), Some(0));
+        assert_eq!(interner.len(), 64);
+        assert!(a.is_map());
+        assert!(b.is_vec());
+
+        for (k, v) in &b {
+            let (ka, va) = a.get_key_value(&**k).expect("shared key");
+            assert!(Arc::ptr_eq(k, ka));
+            assert_eq!(*v, if *va == 100 { 0 } else { va * 2 });
+            // the interner, `a` and `b` each hold one reference
+            assert_eq!(Arc::strong_count(k), 3);
+        }
+    }
+
+    #[test]
+    fn entry_or_insert_promotes() {
+        let mut v = HashMap::new();
+        for i in 0..40 {
+            *v.entry(i).or_insert(i) += 1;
+            assert_eq!(v.is_map(), i >= VEC_LIMIT_UPPER);
+        }
+        assert_eq!(v.len(), 40);
+        for i in 0..40 {
+            assert_eq!(v[&i], i + 1);
+        }
+    }
+
+    #[test]
+    fn raw_entry_promotes() {
+        let mut v = HashMap::new();
+        for i in 0..40 {
+            v.raw_entry_mut().from_key(&i).or_insert(i, i);
+            assert_eq!(v.is_map(), i >= VEC_LIMIT_UPPER);
+        }
+        assert_eq!(v.len(), 40);
+        for i in 0..40 {
+            assert_eq!(v[&i], i);
+        }
+
+        // occupied entries of a full vector don't promote
+        let mut v = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(i, i);
+        }
+        for i in 0..VEC_LIMIT_UPPER {
+            *v.raw_entry_mut().from_key(&i).or_insert(0, 0).1 += 1;
+            v.raw_entry_mut().from_hash(0, |k| *k == i).insert(i, i + 2);
+        }
+        assert!(v.is_vec());
+        assert_eq!(v[&3], 5);
+
+        // all ways of inserting into a vacant entry do
+        let inserts: [fn(&mut HashMap<usize, usize>); 3] = [
+            |v| {
+                v.raw_entry_mut().from_key(&100).insert(100, 1);
+            },
+            |v| {
+                v.raw_entry_mut().from_key(&100).or_insert_with(|| (100, 1));
+            },
+            |v| {
+                let h = v.hasher().hash_one(100);
+                if let RawEntryMut::Vacant(e) = v.raw_entry_mut().from_key_hashed_nocheck(h, &100) {
+                    e.insert_hashed_nocheck(h, 100, 1);
+                }
+            },
+        ];
+        for insert in &inserts {
+            let mut v = v.clone();
+            insert(&mut v);
+            assert!(v.is_map());
+            assert_eq!(v.len(), VEC_LIMIT_UPPER + 1);
+            assert_eq!(v.get(&100), Some(&1));
+            assert_eq!(v[&3], 5);
+        }
+    }
+
+    #[test]
+    fn promotion_keeps_reservation() {
+        let mut v = HashMap::vec_with_capacity(100);
+        for i in 0..=VEC_LIMIT_UPPER {
+            v.insert(i, i);
+        }
+        assert!(v.is_map());
+        assert!(v.capacity() >= 100);
+
+        let mut v = HashMap::new();
+        v.reserve(VEC_LIMIT_UPPER);
+        for i in 0..=VEC_LIMIT_UPPER {
+            v.entry(i).or_insert(i);
+        }
+        assert!(v.is_map());
+        assert!(v.capacity() > VEC_LIMIT_UPPER);
+
+        let mut v = HashMap::vec_with_capacity(100);
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(i, i);
+        }
+        assert_eq!(v.checked_insert(VEC_LIMIT_UPPER, 0), Ok(None));
+        assert!(v.is_map());
+        assert!(v.capacity() >= 100);
+    }
+
+    #[test]
+    fn insert_entry_does_not_rescan() {
+        use std::cell::Cell;
+
+        thread_local!(static COMPARISONS: Cell<usize> = const { Cell::new(0) });
+        fn comparisons() -> usize {
+            COMPARISONS.with(Cell::get)
+        }
+
+        #[derive(Debug)]
+        struct Counted(usize);
+        impl Hash for Counted {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                self.0.hash(state);
+            }
+        }
+        impl PartialEq for Counted {
+            fn eq(&self, other: &Self) -> bool {
+                COMPARISONS.with(|c| c.set(c.get() + 1));
+                self.0 == other.0
+            }
+        }
+        impl Eq for Counted {}
+
+        let mut v = HashMap::new();
+        for i in 0..8 {
+            v.insert(Counted(i), i);
+        }
+        assert!(v.is_vec());
+        let before = comparisons();
+        if let Entry::Vacant(e) = v.entry(Counted(8)) {
+            // looking up the entry scans the vector once
+            assert_eq!(comparisons() - before, 8);
+            let mut o = e.insert_entry(8);
+            *o.get_mut() += 1;
+            assert_eq!(o.key().0, 8);
+            assert_eq!(*o.get(), 9);
+            let (k, v) = o.remove_entry();
+            assert_eq!((k.0, v), (8, 9));
+        }
+        assert_eq!(comparisons() - before, 8);
+
+        // modifying an occupied entry of a full vector scans it only once
+        for i in 8..VEC_LIMIT_UPPER {
+            v.insert(Counted(i), i);
+        }
+        assert!(v.is_vec());
+        let before = comparisons();
+        v.entry(Counted(3)).and_modify(|v| *v += 1).or_insert(0);
+        assert_eq!(comparisons() - before, 4);
+        assert!(v.is_vec());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_iter() {
+        use ::rayon::prelude::*;
+
+        let mut v: HashMap<usize, usize> = HashMap::new();
+        for i in 0..100 {
+            v.insert(i, i);
+            if i == 10 || i == 99 {
+                let mut pairs: Vec<(&usize, &usize)> = v.par_iter().collect();
+                let mut expected: Vec<(&usize, &usize)> = v.iter().collect();
+                pairs.sort_unstable();
+                expected.sort_unstable();
+                assert_eq!(pairs, expected);
+
+                v.par_iter_mut().for_each(|(k, v)| *v += k);
+                assert!((0..=i).all(|k| v.get(&k) == Some(&(k * 2))));
+                v.par_iter_mut().for_each(|(k, v)| *v -= k);
+            }
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn from_par_iter() {
+        use ::rayon::prelude::*;
+
+        let m: HashMap<usize, usize> = (0..5000).into_par_iter().map(|i| (i, i * 2)).collect();
+        assert!(m.is_map());
+        assert_eq!(m.len(), 5000);
+        assert!((0..5000).all(|i| m.get(&i) == Some(&(i * 2))));
+
+        let m: HashMap<usize, usize> = (0..10).into_par_iter().map(|i| (i, i)).collect();
+        assert!(m.is_vec());
+        assert_eq!(m.len(), 10);
+
+        // the last value of a key wins
+        let m: HashMap<usize, usize> = (0..5000).into_par_iter().map(|i| (i % 3, i)).collect();
+        assert_eq!(m.len(), 3);
+        assert_eq!(m[&0], 4998);
+        assert_eq!(m[&1], 4999);
+        assert_eq!(m[&2], 4997);
+    }
+
+    #[test]
+    fn vec_drain_order() {
+        let keys = [7, 3, 9, 1, 4, 8, 2];
+        let mut v = HashMap::new();
+        for k in &keys {
+            v.insert(*k, *k * 10);
+        }
+        assert!(v.is_vec());
+        let drained: Vec<_> = v.clone().drain().map(|(k, _)| k).collect();
+        assert_eq!(drained, keys);
+        let drained: Vec<_> = v.drain_in_order().collect();
+        assert_eq!(
+            drained,
+            keys.iter().map(|k| (*k, *k * 10)).collect::<Vec<_>>()
+        );
+
+        // dropping a partially consumed drain still clears the map
+        for k in &keys {
+            v.insert(*k, *k);
+        }
+        assert_eq!(v.drain().take(2).collect::<Vec<_>>(), [(7, 7), (3, 3)]);
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn duplicates_keep_the_first_value() {
+        let with_duplicates = || {
+            let mut v = HashMap::with_capacity(VEC_LIMIT_UPPER);
+            for i in 0..20 {
+                v.insert_nocheck(i % 7, i);
+            }
+            assert!(v.is_vec());
+            assert!((0..7).all(|i| v.get(&i) == Some(&i)));
+            v
+        };
+        let promotions: [fn(&mut HashMap<usize, usize>); 4] = [
+            |v| v.shrink_to_fit_dedup(),
+            |v| v.hint_large(),
+            |v| v.reserve(100),
+            |v| {
+                for i in 100..120 {
+                    v.insert(i, i);
+                }
+            },
+        ];
+        for (n, promote) in promotions.iter().enumerate() {
+            let mut v = with_duplicates();
+            promote(&mut v);
+            assert_eq!(v.is_map(), n > 0);
+            assert_eq!(v.len(), if n == 3 { 27 } else { 7 });
+            assert!((0..7).all(|i| v.get(&i) == Some(&i)));
+        }
+    }
+
+    #[test]
+    fn shrink_to_fit_dedup() {
+        let mut v = HashMap::with_capacity(VEC_LIMIT_UPPER);
+        for i in 0..20 {
+            v.insert_nocheck(i % 7, i);
+        }
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 20);
+        v.shrink_to_fit_dedup();
+        assert_eq!(v.len(), 7);
+        assert_eq!(v.capacity(), 7);
+        assert!((0..7).all(|i| v.get(&i) == Some(&i)));
+        assert_eq!(
+            v.keys().copied().collect::<Vec<_>>(),
+            (0..7).collect::<Vec<_>>()
+        );
+
+        // without insert_nocheck there is nothing to remove
+        let mut v = HashMap::with_capacity(VEC_LIMIT_UPPER);
+        v.insert(1, 1);
+        assert!(!v.1.maybe_duplicates());
+        v.shrink_to_fit_dedup();
+        assert_eq!(v.capacity(), 1);
+    }
+
+    #[test]
+    fn backend_reporting() {
+        let mut v = HashMap::new();
+        assert_eq!(v.insert_reporting(0, 0), (None, false));
+        assert_eq!(v.insert_reporting(0, 0), (Some(0), false));
+        for i in 1..VEC_LIMIT_UPPER {
+            assert_eq!(v.insert_reporting(i, i), (None, false));
+        }
+        assert!(v.is_vec());
+        assert_eq!(v.insert_reporting(VEC_LIMIT_UPPER, 0), (None, true));
+        assert!(v.is_map());
+        assert_eq!(v.insert_reporting(VEC_LIMIT_UPPER + 1, 0), (None, false));
+
+        assert_eq!(v.retain_reporting(|k, _| *k < 2), v.is_vec());
+        assert_eq!(v.len(), 2);
+        let mut v = HashMap::new();
+        v.insert(1, 1);
+        assert!(!v.retain_reporting(|_, _| false));
+        assert!(v.is_vec());
+    }
+
+    #[test]
+    fn swap_values() {
+        let mut v = HashMap::new();
+        for i in 0..8 {
+            v.insert(i, i * 10);
+        }
+        for map in [false, true] {
+            if map {
+                v.hint_large();
+            }
+            assert!(v.swap_values(&1, &6));
+            assert_eq!((v[&1], v[&6]), (60, 10));
+            assert!(v.swap_values(&6, &1));
+            assert_eq!((v[&1], v[&6]), (10, 60));
+            assert!(v.swap_values(&3, &3));
+            assert!(!v.swap_values(&9, &9));
+            assert!(!v.swap_values(&3, &9));
+            assert!(!v.swap_values(&9, &3));
+            assert_eq!(v[&3], 30);
+        }
+    }
+
+    #[test]
+    fn insert_bounded() {
+        for max in [4, VEC_LIMIT_UPPER + 4] {
+            let mut v = HashMap::new();
+            for i in 0..max {
+                assert_eq!(v.insert_bounded(i, i, max), Ok(None));
+            }
+            assert_eq!(v.insert_bounded(max, 0, max), Err((max, 0)));
+            assert_eq!(v.insert_bounded(0, 1, max), Ok(Some(0)));
+            assert_eq!(v.len(), max);
+            assert_eq!(v.get(&max), None);
+            assert_eq!(v.is_map(), max > VEC_LIMIT_UPPER);
+
+            assert_eq!(v.insert_bounded(max, 0, max + 1), Ok(None));
+            assert_eq!(v.insert_bounded(max + 1, 0, 0), Err((max + 1, 0)));
+            assert_eq!(v.insert_bounded(max, 1, 0), Ok(Some(0)));
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_values_mut() {
+        use ::rayon::prelude::*;
+
+        for n in [VEC_LIMIT_UPPER, 10_000] {
+            let mut m = HashMap::new();
+            for i in 0..n {
+                m.insert(i, i);
+            }
+            assert_eq!(m.is_map(), n > VEC_LIMIT_UPPER);
+            m.par_values_mut().for_each(|v| *v *= 2);
+            assert!(m.iter().all(|(k, v)| *v == k * 2));
+        }
+    }
+
+    #[test]
+    fn from_iter_keep_first() {
+        for n in [8, 100] {
+            let pairs = (0..n).chain(0..n).enumerate().map(|(i, k)| (k, i));
+            let m: HashMap<usize, usize> = HashMap::from_iter_keep_first(pairs.clone());
+            assert_eq!(m.len(), n);
+            assert_eq!(m.is_map(), n > VEC_LIMIT_UPPER);
+            assert!(m.iter().all(|(k, v)| k == v));
+
+            let last: HashMap<usize, usize> = pairs.collect();
+            assert!(last.iter().all(|(k, v)| k + n == *v));
+        }
+
+        // promotes once the keys outgrow the vector
+        let m: HashMap<usize, usize> =
+            HashMap::from_iter_keep_first((0..100).filter(|_| true).map(|k| (k, k)));
+        assert!(m.is_map());
+        assert_eq!(m.len(), 100);
+    }
+
+    #[test]
+    fn get_key_value() {
+        // the stored key is returned, not one that's merely equal to it
+        let mut v: HashMap<Box<str>, usize> = HashMap::new();
+        for i in 0..8 {
+            v.insert(i.to_string().into_boxed_str(), i);
+        }
+        for map in [false, true] {
+            if map {
+                v.hint_large();
+            }
+            let stored = v.keys().find(|k| &***k == "3").expect("stored").as_ptr();
+            let (k, val) = v.get_key_value("3").expect("present");
+            assert_eq!(k.as_ptr(), stored);
+            assert_eq!(*val, 3);
+            assert_eq!(v.get_key_value("8"), None);
+        }
+    }
+
+    #[test]
+    fn get_or_try_insert_with() {
+        let mut v = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            assert_eq!(
+                v.get_or_try_insert_with(i, || Ok::<_, ()>(i)).copied(),
+                Ok(i)
+            );
+        }
+        let before = v.clone();
+        assert_eq!(
+            v.get_or_try_insert_with(VEC_LIMIT_UPPER, || Err("failed")),
+            Err("failed")
+        );
+        assert!(v.is_vec());
+        assert_eq!(v, before);
+
+        // present keys don't call `f`
+        *v.get_or_try_insert_with(3, || Err("failed"))
+            .expect("present") += 1;
+        assert_eq!(v[&3], 4);
+
+        assert_eq!(v.get_or_try_insert_with(100, || Ok::<_, ()>(1)), Ok(&mut 1));
+        assert!(v.is_map());
+        assert_eq!(v.get_or_try_insert_with(101, || Err(())), Err(()));
+        assert_eq!(v.len(), VEC_LIMIT_UPPER + 1);
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut v: HashMap<usize, String> = HashMap::new();
+        for i in 0..8 {
+            v.insert(i, i.to_string());
+        }
+        for map in [false, true] {
+            if map {
+                v.hint_large();
+            }
+            for (k, s) in &mut v {
+                s.push_str(&k.to_string());
+            }
+            assert_eq!((&v).into_iter().count(), 8);
+        }
+        for (k, s) in &v {
+            assert_eq!(*s, k.to_string().repeat(3));
+        }
+
+        // the values are moved out, not cloned
+        let mut owned: Vec<(usize, String)> = v.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned[3], (3, "333".to_string()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_len_prefix() {
+        // MessagePack prefixes maps with their length, so the serializer
+        // has to be given one up front.
+        let mut v: HashMap<u32, u32> = HashMap::new();
+        for i in 0..5 {
+            v.insert(i, i);
+        }
+        let bytes = rmp_serde::to_vec(&v).expect("serialize");
+        assert_eq!(bytes[0], 0x80 | 5);
+
+        for i in 5..1000 {
+            v.insert(i, i);
+        }
+        assert!(v.is_map());
+        let bytes = rmp_serde::to_vec(&v).expect("serialize");
+        assert_eq!(bytes[..3], [0xde, 0x03, 0xe8]);
+
+        for i in 1000..70_000 {
+            v.insert(i, i);
+        }
+        let bytes = rmp_serde::to_vec(&v).expect("serialize");
+        assert_eq!(bytes[0], 0xdf);
+        assert_eq!(
+            u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
+            70_000
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_backend() {
+        let json = format!(
+            "{{{}}}",
+            (0..500)
+                .map(|i| format!("\"{i}\": {i}"))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let v: HashMap<String, usize> = serde_json::from_str(&json).expect("deserialize");
+        assert!(v.is_map());
+        assert_eq!(v.len(), 500);
+        assert_eq!(v.get("499"), Some(&499));
+
+        // MessagePack knows the length up front, so the map backend is picked
+        // before anything is inserted
+        let big: HashMap<usize, usize> = (0..500).map(|i| (i, i)).collect();
+        let bytes = rmp_serde::to_vec(&big).expect("serialize");
+        let v: HashMap<usize, usize> = rmp_serde::from_slice(&bytes).expect("deserialize");
+        assert!(v.is_map());
+        assert_eq!(v, big);
+
+        let v: HashMap<String, usize> =
+            serde_json::from_str(r#"{"a": 1, "b": 2}"#).expect("deserialize");
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_vec_order() {
+        let keys = ["zebra", "apple", "mango", "kiwi"];
+        let mut v: HashMap<String, usize> = HashMap::new();
+        for (i, k) in keys.iter().enumerate() {
+            v.insert((*k).to_string(), i);
+        }
+        assert!(v.is_vec());
+
+        // a MessagePack map is its length followed by the encoded keys and
+        // values
+        let mut expected = vec![0x80 | 4];
+        for (i, k) in keys.iter().enumerate() {
+            expected.extend(rmp_serde::to_vec(k).expect("serialize"));
+            expected.extend(rmp_serde::to_vec(&i).expect("serialize"));
+        }
+        let bytes = rmp_serde::to_vec(&v).expect("serialize");
+        assert_eq!(bytes, expected);
+
+        let back: HashMap<String, usize> = rmp_serde::from_slice(&bytes).expect("deserialize");
+        assert_eq!(back, v);
+        assert_eq!(rmp_serde::to_vec(&back).expect("serialize"), expected);
+    }
+
+    #[test]
+    fn into_keys_values() {
+        let mut v: HashMap<usize, String> = HashMap::new();
+        for i in 0..40 {
+            v.insert(i, i.to_string());
+            if i == 3 || i == 39 {
+                let mut keys = v.clone().into_keys();
+                assert_eq!(keys.len(), i + 1);
+                keys.next();
+                assert_eq!(keys.size_hint(), (i, Some(i)));
+                let mut keys: Vec<_> = keys.collect();
+                keys.sort_unstable();
+                assert_eq!(keys.len(), i);
+
+                let values = v.clone().into_values();
+                assert_eq!(values.len(), i + 1);
+                let mut values: Vec<String> = values.collect();
+                values.sort_unstable_by_key(|s| s.parse::<usize>().ok());
+                assert_eq!(values, (0..=i).map(|i| i.to_string()).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn max_vec_capacity() {
+        for limit in [0, 8, 100] {
+            let mut v = HashMap::with_max_vec_capacity(limit);
+            for i in 0..limit {
+                v.insert(i, i);
+            }
+            assert!(v.is_vec());
+            assert_eq!(v.len(), limit);
+
+            let mut c = v.clone();
+            assert_eq!(c.max_vec_capacity(), limit);
+            c.entry(limit).or_insert(0);
+            assert!(c.is_map());
+
+            let mut c = v.clone();
+            c.raw_entry_mut().from_key(&limit).or_insert(limit, 0);
+            assert!(c.is_map());
+
+            let mut c = v.clone();
+            assert_eq!(c.checked_insert(limit, 0), Ok(None));
+            assert!(c.is_map());
+
+            v.reserve(1);
+            assert!(v.is_map());
+            assert_eq!(v.max_vec_capacity(), limit);
+        }
+
+        let mut v = HashMap::with_max_vec_capacity(100);
+        for i in 0..100 {
+            v.insert(i, i);
+        }
+        assert!(v.is_vec());
+        v.insert(100, 100);
+        assert!(v.is_map());
+        v.remove(&100);
+        assert!(v.clone_as_vec().is_vec());
+    }
+
+    #[test]
+    fn reserve_keeps_map_memory() {
+        // maps sized for more than a vector holds are hinted to be large
+        let mut v = HashMap::with_capacity(100);
+        for i in 0..100 {
+            v.insert(i, i);
+        }
+        for i in 10..100 {
+            v.remove(&i);
+        }
+        let capacity = v.capacity();
+        v.reserve(1);
+        assert!(v.is_map());
+        assert_eq!(v.capacity(), capacity);
+        assert!((0..10).all(|i| v.get(&i) == Some(&i)));
+
+        let mut v = HashMap::with_capacity(1000);
+        v.extend((0..100).filter(|i| i % 10 == 0).map(|i| (i, i)));
+        assert!(v.is_map());
+        assert!(v.capacity() >= 1000);
+
+        let mut v: HashMap<usize, usize> = (0..1000).map(|i| (i, i)).collect();
+        let capacity = v.capacity();
+        v.collect_into((0..3).map(|i| (i, i)));
+        assert!(v.is_map());
+        assert_eq!(v.len(), 3);
+        assert_eq!(v.capacity(), capacity);
+    }
+
+    #[test]
+    fn iter_from_both_ends() {
+        for n in [4, 100] {
+            let mut v: HashMap<usize, usize> = (0..n).map(|i| (i, i)).collect();
+            let forward: Vec<_> = v.keys().copied().collect();
+            let mut backward: Vec<_> = v.keys().rev().copied().collect();
+            backward.reverse();
+            assert_eq!(forward, backward);
+
+            let mut it = v.iter();
+            let first = it.next();
+            let last = it.next_back();
+            assert_eq!(it.size_hint(), (n - 2, Some(n - 2)));
+            let mut middle: Vec<_> = it.rev().collect();
+            middle.reverse();
+            let mut all: Vec<_> = first.into_iter().collect();
+            all.extend(middle);
+            all.extend(last);
+            assert!(all.iter().map(|(k, _)| **k).eq(forward.iter().copied()));
+
+            for val in v.values_mut().rev().take(2) {
+                *val += 1000;
+            }
+            let values: Vec<_> = v.values().rev().copied().collect();
+            assert!(values[..2].iter().all(|val| *val >= 1000));
+            assert!(values[2..].iter().all(|val| *val < 1000));
+            let mut pairs: Vec<_> = v.iter_mut().rev().map(|(k, _)| *k).collect();
+            pairs.reverse();
+            assert_eq!(pairs, forward);
+        }
+    }
+
+    #[test]
+    fn hints_stay_small() {
+        assert!(std::mem::size_of::<Hints>() <= 2 * std::mem::size_of::<usize>());
+        let mut v: HashMap<usize, usize> = HashMap::new();
+        v.hint_additional(0);
+        assert!(v.1.knobs.is_none());
+        for i in 0..40 {
+            v.insert_nocheck(i, i);
+        }
+        assert!(v.1.maybe_duplicates());
+        assert!(v.1.knobs.is_none());
+        let mut v: HashMap<usize, usize> = HashMap::with_max_vec_capacity(4);
+        v.hint_additional(100);
+        assert_eq!((v.1.vec_limit(), v.1.headroom()), (4, 100));
+        for i in 0..5 {
+            v.insert(i, i);
+        }
+        assert!(v.is_map());
+        assert!(v.capacity() >= 105);
+        assert_eq!(v.clone().1.vec_limit(), 4);
+    }
+
+    #[test]
+    fn reserve_moves_small_maps_back() {
+        let shrunk = || {
+            let mut v: HashMap<usize, usize> = (0..100).map(|i| (i, i)).collect();
+            for i in 4..100 {
+                v.remove(&i);
+            }
+            assert!(v.is_map());
+            v
+        };
+        let mut v = shrunk();
+        v.reserve(10);
+        assert!(v.is_vec());
+        assert!(v.capacity() >= 14);
+        assert_eq!((v.len(), v[&3]), (4, 3));
+
+        // room for more than fits into a vector keeps the map
+        let mut v = shrunk();
+        v.reserve(40);
+        assert!(v.is_map());
+        v.reserve(1);
+        assert!(v.is_map());
+
+        let mut v = shrunk();
+        v.hint_large();
+        v.reserve(1);
+        assert!(v.is_map());
+    }
+
+    #[test]
+    fn large_capacity_hints_large() {
+        let mut v = HashMap::with_capacity(1000);
+        v.reserve(1);
+        v.insert(1, 1);
+        assert!(v.is_map());
+        assert!(v.capacity() >= 1000);
+        v.compact();
+        assert!(v.is_map());
+
+        let mut v = HashMap::new();
+        v.insert(1, 1);
+        v.reserve(1000);
+        assert!(v.is_map());
+        v.compact();
+        assert!(v.is_map());
+
+        let mut v = HashMap::with_capacity_and_hasher(1000, DefaultHashBuilder::default());
+        v.insert(1, 1);
+        v.compact();
+        assert!(v.is_map());
+
+        let mut v: HashMap<usize, usize> = (0..100).map(|i| (i, i)).collect();
+        v.retain(|k, _| *k < 10);
+        v.compact();
+        assert!(v.is_vec());
+
+        let mut v: HashMap<usize, usize> = HashMap::from_sorted_iter((0..100).map(|i| (i, i)));
+        v.retain(|k, _| *k < 10);
+        v.compact();
+        assert!(v.is_vec());
+    }
+
+    #[test]
+    fn demotion_keeps_hasher() {
+        use std::collections::hash_map::RandomState;
+
+        let mut v = HashMap::with_hasher(RandomState::new());
+        for i in 0..100 {
+            v.insert(i, i);
+        }
+        let before = v.hasher().hash_one(42);
+        assert!(v.retain_reporting(|k, _| *k < 4));
+        assert_eq!(v.hasher().hash_one(42), before);
+
+        v.insert_nocheck(100, 100);
+        v.compact();
+        assert!(v.is_vec());
+        assert_eq!(v.hasher().hash_one(42), before);
+    }
+
+    #[test]
+    fn or_insert_tracked() {
+        let mut v = HashMap::with_max_vec_capacity(4);
+        for i in 0..8 {
+            let (x, len) = v.entry(i).or_insert_tracked(i);
+            assert_eq!((*x, len), (i, i + 1));
+            assert_eq!(v.len(), i + 1);
+        }
+        assert!(v.is_map());
+        for i in 0..8 {
+            let (x, len) = v.entry(i).or_insert_tracked(0);
+            assert_eq!((*x, len), (i, 8));
+        }
+        assert_eq!(v.entry(3).and_remove_if(|_| true).or_insert_tracked(9).1, 8);
+        assert_eq!(
+            v.entry(3).and_remove_if(|_| false).or_insert_tracked(0).1,
+            8
+        );
+        assert_eq!(v.len(), 8);
+    }
+
+    #[test]
+    fn retain_downgrades() {
+        let mut v: HashMap<usize, usize> = (0..100).map(|i| (i, i)).collect();
+        v.retain(|k, _| *k <= VEC_LIMIT_UPPER);
+        assert!(v.is_map());
+        assert!(v.retain_reporting(|k, _| *k < VEC_LIMIT_UPPER));
+        assert!(v.is_vec());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER);
+        assert!((0..VEC_LIMIT_UPPER).all(|i| v.get(&i) == Some(&i)));
+        assert!(!v.retain_reporting(|k, _| *k < 4));
+        assert_eq!(v.len(), 4);
+
+        // plain retain moves the map back as well
+        let mut v: HashMap<usize, usize> = (0..100).map(|i| (i, i)).collect();
+        v.retain(|k, _| *k < 4);
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 4);
+        assert_eq!(v.get(&3), Some(&3));
+
+        // a map hinted to be large stays map backed
+        let mut v: HashMap<usize, usize> = (0..100).map(|i| (i, i)).collect();
+        v.hint_large();
+        assert!(!v.retain_reporting(|k, _| *k < 4));
+        assert!(v.is_map());
+        assert_eq!(v.len(), 4);
+    }
+
+    #[test]
+    fn raw_entry_returning_index() {
+        let mut v = HashMap::new();
+        for i in 0..8 {
+            v.insert(i, i * 10);
+        }
+        for i in 0..8 {
+            let found = v.raw_entry().from_key_returning_index(&i);
+            let idx = found.and_then(|(idx, _, _)| idx);
+            assert_eq!(found.map(|(_, k, v)| (k, v)), Some((&i, &(i * 10))));
+            assert_eq!(idx.and_then(|idx| v.iter().nth(idx)), Some((&i, &(i * 10))));
+        }
+        assert_eq!(v.raw_entry().from_key_returning_index(&8), None);
+
+        for i in 8..100 {
+            v.insert(i, i * 10);
+        }
+        assert!(v.is_map());
+        assert_eq!(
+            v.raw_entry().from_key_returning_index(&42),
+            Some((None, &42, &420))
+        );
+        assert_eq!(v.raw_entry().from_key_returning_index(&100), None);
+    }
+
+    #[test]
+    fn remove_entry() {
+        let mut v: HashMap<String, usize> = HashMap::new();
+        for i in 0..4 {
+            v.insert(i.to_string(), i);
+        }
+        let (k, val) = v.remove_entry("1").expect("key is present");
+        assert_eq!((k.as_str(), val), ("1", 1));
+        assert_eq!(v.remove_entry("1"), None);
+        assert_eq!(v.len(), 3);
+        assert!(["0", "2", "3"].iter().all(|k| v.contains_key(*k)));
+
+        for i in 4..100 {
+            v.insert(i.to_string(), i);
+        }
+        assert!(v.is_map());
+        assert_eq!(v.remove_entry("42"), Some(("42".to_string(), 42)));
+        assert_eq!(v.remove_entry("42"), None);
+        assert_eq!(v.len(), 98);
+    }
+
+    #[test]
+    fn compact() {
+        // promoted and shrunk
+        let mut v: HashMap<usize, usize> = (0..100).map(|i| (i, i)).collect();
+        for i in 8..100 {
+            v.remove(&i);
+        }
+        assert!(v.is_map());
+        v.compact();
+        assert!(v.is_vec());
+        assert_eq!(v.capacity(), 8);
+        assert!((0..8).all(|i| v.get(&i) == Some(&i)));
+
+        // duplicates from insert_nocheck
+        v.reserve(8);
+        for i in 0..8 {
+            v.insert_nocheck(i, 100 + i);
+        }
+        assert_eq!(v.len(), 16);
+        v.compact();
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 8);
+        assert_eq!(v.capacity(), 8);
+        assert!((0..8).all(|i| v.get(&i) == Some(&i)));
+
+        // vectors grown past the limit are promoted
+        let mut v: HashMap<usize, usize> = HashMap::vec_with_capacity(100);
+        for i in 0..100 {
+            v.insert_nocheck(i, i);
+        }
+        v.compact();
+        assert!(v.is_map());
+        assert_eq!(v.len(), 100);
+        assert!((0..100).all(|i| v.get(&i) == Some(&i)));
+    }
+
+    #[test]
+    fn extend() {
+        let mut v = HashMap::new();
+        v.extend((0..16).map(|i| (i, i)));
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 16);
+
+        // the iterator gives no lower bound, so the map is promoted while
+        // extending
+        v.extend((16..64).filter(|_| true).map(|i| (i, i)));
+        assert!(v.is_map());
+        assert_eq!(v.len(), 64);
+
+        // updates to present keys only never promote the map
+        let mut v: HashMap<usize, usize> = (0..20).map(|i| (i, i)).collect();
+        v.extend((0..20).map(|i| (i, i + 1)));
+        assert!(v.is_vec());
+        assert_eq!((v.len(), v[&19]), (20, 20));
+
+        let other: HashMap<usize, usize> = (0..8).map(|i| (i, i * 10)).collect();
+        let mut v: HashMap<usize, usize> = HashMap::new();
+        v.extend(&other);
+        v.extend(&other);
+        assert_eq!(v, other);
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        let v: HashMap<usize, usize> = HashMap::from_sorted_iter((0..8).map(|i| (i, i)));
+        assert!(v.is_vec());
+        assert!(!v.1.maybe_duplicates());
+        assert_eq!(v.len(), 8);
+        assert!((0..8).all(|i| v.get(&i) == Some(&i)));
+
+        let v: HashMap<usize, usize> =
+            HashMap::from_sorted_iter((0..100).filter(|_| true).map(|i| (i, i)));
+        assert!(v.is_map());
+        assert_eq!(v.len(), 100);
+        assert!((0..100).all(|i| v.get(&i) == Some(&i)));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "must be sorted and unique")]
+    fn from_sorted_iter_unsorted() {
+        let _: HashMap<usize, usize> = HashMap::from_sorted_iter(vec![(1, 1), (3, 3), (2, 2)]);
+    }
+
+    #[test]
+    fn collect_presized() {
+        let v: HashMap<usize, usize> = (0..VEC_LIMIT_UPPER).map(|i| (i, i)).collect();
+        assert!(v.is_vec());
+        assert!(v.capacity() >= VEC_LIMIT_UPPER);
+
+        // large iterators go straight to the map backend, the map never
+        // had to grow
+        let v: HashMap<usize, usize> = (0..1000).map(|i| (i, i)).collect();
+        assert!(v.is_map());
+        assert_eq!(
+            v.capacity(),
+            HashMap::<usize, usize>::with_capacity(1000).capacity()
+        );
+        assert!((0..1000).all(|i| v.get(&i) == Some(&i)));
+    }
+
+    #[test]
+    fn from_array() {
+        let v: HashMap<u8, u8> = HashMap::from([]);
+        assert!(v.is_vec());
+        let v: HashMap<usize, usize> = HashMap::from([(1, 1), (2, 2), (1, 3)]);
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 2);
+        assert_eq!(v[&1], 3);
+
+        let mut pairs = [(0, 0); VEC_LIMIT_UPPER + 1];
+        for (i, p) in pairs.iter_mut().enumerate() {
+            *p = (i, i);
+        }
+        let v: HashMap<usize, usize> = HashMap::from(pairs);
+        assert!(v.is_map());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER + 1);
+    }
+
+    #[test]
+    fn try_for_each_value() {
+        for n in [8, 100] {
+            let mut v: HashMap<usize, usize> = (0..n).map(|i| (i, 0)).collect();
+            let mut visited = 0;
+            let r = v.try_for_each_value(|k, v| {
+                visited += 1;
+                *v = 1;
+                if visited == 4 {
+                    ControlFlow::Break(*k)
+                } else {
+                    ControlFlow::Continue(())
+                }
+            });
+            assert!(r.is_break());
+            assert_eq!(visited, 4);
+            assert_eq!(v.values().filter(|v| **v == 1).count(), 4);
+        }
+    }
+
+    #[test]
+    fn vec_with_capacity_promotes() {
+        let mut v = HashMap::vec_with_capacity(128);
+        assert_eq!(v.max_vec_capacity(), VEC_LIMIT_UPPER);
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(i, i);
+        }
+        assert!(v.is_vec());
+        v.insert(VEC_LIMIT_UPPER, VEC_LIMIT_UPPER);
+        assert!(v.is_map());
+        assert!(v.capacity() >= 128);
+
+        let mut v = HashMap::fixed_vec_with_capacity(128);
+        for i in 0..256 {
+            v.insert(i, i);
+            v.entry(i + 1000).or_insert(i);
+        }
+        v.reserve(1000);
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 512);
+        assert!(v.clone().is_vec());
+        assert!((0..256).all(|i| v.get(&i) == Some(&i)));
+    }
+
+    #[test]
+    fn get_many_mut() {
+        let mut v: HashMap<String, usize> = HashMap::new();
+        for n in [4, 100] {
+            for i in 0..n {
+                v.insert(i.to_string(), i);
+            }
+            if let Some([a, b, c]) = v.get_many_mut(["0", "3", "2"]) {
+                *a += 100;
+                *b += 100;
+                *c += 100;
+            }
+            assert_eq!([v["0"], v["1"], v["2"], v["3"]], [100, 1, 102, 103]);
+            assert!(v.get_many_mut(["0", "3", "0"]).is_none());
+            assert!(v.get_many_mut(["0", "missing"]).is_none());
+            assert!(v.get_many_mut::<str, 0>([]).is_some());
+        }
+    }
+
+    #[test]
+    fn entry_by() {
+        // hashes strings ignoring ascii case, so it agrees with a case
+        // insensitive comparator
+        #[derive(Clone, Default)]
+        struct FoldCase;
+        struct FoldCaseHasher(std::collections::hash_map::DefaultHasher);
+        impl BuildHasher for FoldCase {
+            type Hasher = FoldCaseHasher;
+            fn build_hasher(&self) -> FoldCaseHasher {
+                FoldCaseHasher(std::collections::hash_map::DefaultHasher::new())
+            }
         }
+        impl Hasher for FoldCaseHasher {
+            fn finish(&self) -> u64 {
+                self.0.finish()
+            }
+            fn write(&mut self, bytes: &[u8]) {
+                for b in bytes {
+                    self.0.write_u8(b.to_ascii_lowercase());
+                }
+            }
+        }
+
+        for large in [false, true] {
+            let mut v: HashMap<String, usize, FoldCase> = HashMap::vec_with_hasher(FoldCase);
+            if large {
+                v.hint_large();
+            }
+            for word in ["Cap", "GILL", "spore", "cap", "gill", "SPORE", "Spore"] {
+                *v.entry_by(word.to_string(), |k| k.eq_ignore_ascii_case(word))
+                    .or_insert(0) += 1;
+            }
+            assert_eq!(v.is_map(), large);
+            assert_eq!(v.len(), 3);
+            assert_eq!((v["Cap"], v["GILL"], v["spore"]), (2, 2, 3));
+
+            if let Entry::Occupied(mut e) = v.entry_by("CAP".to_string(), |k| k == "Cap") {
+                *e.get_mut() += 1;
+            }
+            assert_eq!(v["Cap"], 3);
+            assert!(matches!(
+                v.entry_by("cap".to_string(), |_| false),
+                Entry::Vacant(_)
+            ));
+        }
+
+        // a comparator that disagrees with the hash finds nothing on
+        // either backend
+        for large in [false, true] {
+            let mut v: HashMap<String, usize> = HashMap::new();
+            if large {
+                v.hint_large();
+            }
+            v.insert("spore".to_string(), 1);
+            assert!(matches!(
+                v.entry_by("SPORE".to_string(), |k| k.eq_ignore_ascii_case("SPORE")),
+                Entry::Vacant(_)
+            ));
+        }
+
+        // inserting into a full vector through a vacant entry promotes it
+        let mut v: HashMap<String, usize, FoldCase> = HashMap::vec_with_hasher(FoldCase);
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(format!("k{i}"), i);
+        }
+        assert!(v.is_vec());
+        assert!(matches!(
+            v.entry_by("K0".to_string(), |k| k.eq_ignore_ascii_case("K0")),
+            Entry::Occupied(_)
+        ));
+        assert!(v.is_vec());
+        v.entry_by("NEW".to_string(), |k| k.eq_ignore_ascii_case("NEW"))
+            .or_insert(0);
+        assert!(v.is_map());
+        assert_eq!(v["NEW"], 0);
     }
 
-    /// Creates a raw immutable entry builder for the `HashMap`.
-    ///
-    /// Raw entries provide the lowest level of control for searching and
-    /// manipulating a map. They must be manually initialized with a hash and
-    /// then manually searched.
-    ///
-    /// This is useful for
-    /// * Hash memoization
-    /// * Using a search key that doesn't work with the Borrow trait
-    /// * Using custom comparison logic without newtype wrappers
-    ///
-    /// Unless you are in such a situation, higher-level and more foolproof APIs like
-    /// `get` should be preferred.
-    ///
-    /// Immutable raw entries have very limited use; you might instead want `raw_entry_mut`.
-    #[inline]
-    pub fn raw_entry(&self) -> RawEntryBuilder<'_, K, V, S> {
-        match &self.0 {
-            HashMapInt::Vec(m) => RawEntryBuilder::from(m.raw_entry()),
-            HashMapInt::Map(m) => RawEntryBuilder::from(m.raw_entry()),
-            HashMapInt::None => unreachable!(),
+    #[test]
+    fn retain_indexed() {
+        for n in [8, 100] {
+            let mut v: HashMap<usize, usize> = HashMap::new();
+            for i in 0..n {
+                v.insert(i * 7 % n, i);
+            }
+            let order: Vec<usize> = v.keys().copied().collect();
+            let mut seen = Vec::new();
+            v.retain_indexed(|i, k, _| {
+                seen.push(i);
+                assert_eq!(order[i], *k);
+                i % 2 == 0
+            });
+            assert_eq!(seen, (0..n).collect::<Vec<_>>());
+            assert_eq!(v.len(), n / 2);
+            let kept: Vec<usize> = order.iter().copied().step_by(2).collect();
+            assert!(kept.iter().all(|k| v.contains_key(k)));
         }
     }
-}
 
-impl<K, V, S, S1> PartialEq<HashMap<K, V, S1>> for HashMap<K, V, S>
-where
-    K: Eq + Hash,
-    V: PartialEq,
-    S1: BuildHasher,
-{
-    fn eq(&self, other: &HashMap<K, V, S1>) -> bool {
-        if self.len() != other.len() {
-            return false;
+    #[test]
+    fn try_reserve() {
+        let mut v: HashMap<u64, u64> = HashMap::new();
+        v.insert(1, 1);
+        assert_eq!(v.try_reserve(4), Ok(()));
+        assert!(v.is_vec());
+        assert!(v.capacity() >= 5);
+        assert_eq!(
+            v.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert!(v.is_vec());
+        assert_eq!(v.try_reserve(VEC_LIMIT_UPPER), Ok(()));
+        assert!(v.is_map());
+        assert!(v.capacity() > VEC_LIMIT_UPPER);
+        assert_eq!(
+            v.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(v.try_reserve(0), Ok(()));
+        assert!(v.is_map());
+        assert_eq!(v.get(&1), Some(&1));
+    }
+
+    #[test]
+    fn try_promotion_keeps_hasher() {
+        use std::collections::hash_map::RandomState;
+
+        let mut v = HashMap::vec_with_hasher(RandomState::new());
+        v.insert(1, 1);
+        let before = v.hasher().hash_one(42);
+        assert_eq!(v.try_reserve(100), Ok(()));
+        assert!(v.is_map());
+        assert_eq!(v.hasher().hash_one(42), before);
+
+        let mut v = HashMap::vec_with_hasher(RandomState::new());
+        let before = v.hasher().hash_one(42);
+        for i in 0..=VEC_LIMIT_UPPER {
+            assert_eq!(v.checked_insert(i, i), Ok(None));
         }
+        assert!(v.is_map());
+        assert_eq!(v.hasher().hash_one(42), before);
+        assert!((0..=VEC_LIMIT_UPPER).all(|i| v.get(&i) == Some(&i)));
+    }
 
-        self.iter()
-            .all(|(key, value)| other.get(key).map_or(false, |v| *value == *v))
+    #[test]
+    fn replace_key() {
+        use std::rc::Rc;
+
+        for n in [4, VEC_LIMIT_UPPER, 100] {
+            let mut v: HashMap<Rc<String>, usize> = HashMap::new();
+            for i in 0..n {
+                v.insert(Rc::new(i.to_string()), i);
+            }
+            let shared: Vec<Rc<String>> = (0..n).map(|i| Rc::new(i.to_string())).collect();
+            for k in &shared {
+                let old = v.replace_key(Rc::clone(k));
+                assert!(old.is_some_and(|old| Rc::strong_count(&old) == 1 && old == *k));
+                assert_eq!(Rc::strong_count(k), 2);
+            }
+            assert_eq!(v.replace_key(Rc::new("missing".to_string())), None);
+            assert_eq!(v.len(), n);
+            assert!(v.keys().all(|k| Rc::strong_count(k) == 2));
+            assert!((0..n).all(|i| v.get(&i.to_string()) == Some(&i)));
+        }
     }
-}
 
-//#[derive(Clone)]
-/// Iterator over the keys
-pub struct Keys<'a, K, V> {
-    inner: Iter<'a, K, V>,
-}
+    #[test]
+    fn upsert_many() {
+        let mut v: HashMap<usize, Vec<usize>> = HashMap::new();
+        for i in 0..4 {
+            v.insert(i, vec![i]);
+        }
+        v.upsert_many((2..8).map(|i| (i, vec![i * 10])), |have, mut add| {
+            have.append(&mut add);
+        });
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 8);
+        assert_eq!(v[&1], [1]);
+        assert_eq!(v[&3], [3, 30]);
+        assert_eq!(v[&7], [70]);
 
-impl<'a, K, V> Iterator for Keys<'a, K, V> {
-    type Item = &'a K;
+        // a batch adding more keys than fit into the vector promotes the map
+        v.upsert_many((0..100).map(|i| (i, vec![i])), |have, mut add| {
+            have.append(&mut add);
+        });
+        assert!(v.is_map());
+        assert_eq!(v.len(), 100);
+        assert_eq!(v[&3], [3, 30, 3]);
+        assert_eq!(v[&50], [50]);
 
-    #[inline]
-    fn next(&mut self) -> Option<&'a K> {
-        self.inner.next().map(|(k, _)| k)
+        // updates to present keys only never promote the map
+        let mut v: HashMap<usize, usize> = (0..20).map(|i| (i, i)).collect();
+        v.upsert_many((0..20).map(|i| (i, 1)), |have, add| *have += add);
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 20);
+        assert_eq!(v[&19], 20);
+
+        // an empty map is sized for the whole batch up front
+        let mut v: HashMap<usize, usize> = HashMap::new();
+        v.upsert_many((0..100).map(|i| (i, i)), |have, add| *have += add);
+        assert!(v.is_map());
+        assert!(v.capacity() >= 100);
     }
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.inner.size_hint()
+
+    #[test]
+    fn shrink_to() {
+        let mut v = HashMap::vec_with_capacity(VEC_LIMIT_UPPER);
+        v.insert(1, 1);
+        v.shrink_to(8);
+        assert!(v.is_vec());
+        assert_eq!(v.capacity(), 8);
+        v.shrink_to(100);
+        assert_eq!(v.capacity(), 8);
+        v.shrink_to(0);
+        assert_eq!(v.capacity(), 1);
+
+        let mut v: HashMap<usize, usize> = (0..1000).map(|i| (i, i)).collect();
+        v.retain(|k, _| *k < 100);
+        let before = v.capacity();
+        v.shrink_to(500);
+        assert!(v.is_map());
+        assert!(v.capacity() >= 500 && v.capacity() < before);
+        assert!((0..100).all(|i| v.get(&i) == Some(&i)));
     }
-}
 
-//#[derive(Clone)]
-/// Iterator over the values
-pub struct Values<'a, K, V> {
-    inner: Iter<'a, K, V>,
-}
-impl<'a, K, V> Iterator for Values<'a, K, V> {
-    type Item = &'a V;
+    #[test]
+    fn lookup() {
+        let mut v: HashMap<String, usize> = HashMap::new();
+        for i in 0..=VEC_LIMIT_UPPER {
+            let key = i.to_string();
+            let hashed = v.hashed_key(key.as_str());
+            v.insert(key.clone(), i);
+            assert_eq!(v.lookup(key.as_str()), Some(&i));
+            assert_eq!(v.lookup(hashed), Some(&i));
+            assert_eq!(v.lookup(LookupKey::from(key.as_str())), v.get(key.as_str()));
+        }
+        assert!(v.is_map());
+        assert_eq!(v.lookup("nope"), None);
+        assert_eq!(v.lookup(v.hashed_key("nope")), None);
 
-    #[inline]
-    fn next(&mut self) -> Option<&'a V> {
-        self.inner.next().map(|(_, v)| v)
+        let hashed = HashedKey::new(v.hashed_key("1").hash(), "1");
+        assert_eq!(hashed.key(), "1");
+        assert_eq!(v.lookup(hashed), Some(&1));
     }
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.inner.size_hint()
+
+    #[test]
+    fn hashed_key_across_backends() {
+        use std::collections::hash_map::RandomState;
+
+        let mut v: HashMap<usize, usize, RandomState> =
+            HashMap::vec_with_hasher(RandomState::new());
+        v.insert(1, 1);
+        let hashed = v.hashed_key(&1);
+        v.try_reserve(100).expect("reserve");
+        assert!(v.is_map());
+        assert_eq!(v.lookup(hashed), Some(&1));
+
+        let mut v: HashMap<usize, usize, RandomState> =
+            HashMap::vec_with_hasher(RandomState::new());
+        for i in 0..=VEC_LIMIT_UPPER {
+            v.insert(i, i);
+        }
+        assert!(v.is_map());
+        let hashed = v.hashed_key(&1);
+        assert_eq!(v.lookup(hashed), Some(&1));
+        v.retain_reporting(|k, _| *k < 10);
+        assert!(v.is_vec());
+        assert_eq!(v.lookup(hashed), Some(&1));
+        for i in 10..=VEC_LIMIT_UPPER {
+            v.insert(i, i);
+        }
+        assert!(v.is_map());
+        assert_eq!(v.lookup(hashed), Some(&1));
+        assert_eq!(v.clone().lookup(hashed), Some(&1));
     }
-}
 
-//#[derive(Clone)]
-/// Mutable iterator over the values
-pub struct ValuesMut<'a, K, V> {
-    inner: IterMut<'a, K, V>,
-}
+    #[test]
+    fn eq_and_hash() {
+        fn hash_of<T: Hash>(t: &T) -> u64 {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            t.hash(&mut hasher);
+            hasher.finish()
+        }
 
-impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
-    type Item = &'a mut V;
+        let forward: HashMap<usize, usize> = (0..10).map(|i| (i, i)).collect();
+        let mut backward = HashMap::with_capacity(VEC_LIMIT_UPPER + 1);
+        for i in (0..10).rev() {
+            backward.insert(i, i);
+        }
+        assert!(forward.is_vec());
+        assert!(backward.is_map());
+        assert_eq!(forward, backward);
+        assert_eq!(hash_of(&forward), hash_of(&backward));
 
-    #[inline]
-    fn next(&mut self) -> Option<&'a mut V> {
-        self.inner.next().map(|(_, v)| v)
+        let mut changed = forward.clone();
+        changed.insert(3, 4);
+        assert_ne!(hash_of(&forward), hash_of(&changed));
+
+        let mut set = std::collections::HashSet::new();
+        assert!(set.insert(forward));
+        assert!(!set.insert(backward));
+        assert!(set.insert(changed));
+        assert!(set.insert(HashMap::new()));
     }
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.inner.size_hint()
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics() {
+        let promotions = metrics::promotions();
+        let demotions = metrics::demotions();
+        let mut v = HashMap::new();
+        for i in 0..=VEC_LIMIT_UPPER {
+            v.insert(i, i);
+        }
+        assert!(v.is_map());
+        assert!(metrics::promotions() > promotions);
+
+        assert!(v.retain_reporting(|k, _| *k < 4));
+        assert!(v.is_vec());
+        assert!(metrics::demotions() > demotions);
     }
-}
 
-/// Drains the map
-pub struct Drain<'a, K, V>(DrainInt<'a, K, V>);
+    #[test]
+    fn entry_hashes_once() {
+        std::thread_local! {
+            static HASHES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+        }
+        #[derive(PartialEq, Eq)]
+        struct Counted(usize);
+        impl Hash for Counted {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                HASHES.with(|h| h.set(h.get() + 1));
+                self.0.hash(state);
+            }
+        }
+        fn hashes<F: FnOnce()>(f: F) -> usize {
+            let before = HASHES.with(std::cell::Cell::get);
+            f();
+            HASHES.with(std::cell::Cell::get) - before
+        }
 
-enum DrainInt<'a, K, V> {
-    Map(hashbrown::hash_map::Drain<'a, K, V>),
-    Vec(std::vec::Drain<'a, (K, V)>),
-}
+        let mut v = HashMap::with_capacity(VEC_LIMIT_UPPER * 4);
+        for i in 0..VEC_LIMIT_UPPER * 2 {
+            v.insert(Counted(i), i);
+        }
+        assert!(v.is_map());
+        let capacity = v.capacity();
 
-impl<'a, K, V> Iterator for Drain<'a, K, V> {
-    type Item = (K, V);
-    #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
-        match &mut self.0 {
-            DrainInt::Map(m) => m.next(),
-            DrainInt::Vec(m) => m.next(),
+        assert_eq!(hashes(|| *v.entry(Counted(1)).or_insert(0) += 1), 1);
+        assert_eq!(
+            hashes(|| {
+                v.entry(Counted(2))
+                    .and_modify(|v| *v += 1)
+                    .and_modify_with_key(|_, v| *v += 1)
+                    .or_insert(0);
+            }),
+            1
+        );
+        assert_eq!(
+            hashes(|| {
+                v.entry(Counted(1000))
+                    .and_modify(|v| *v += 1)
+                    .or_insert_with(|| 7);
+            }),
+            1
+        );
+        assert_eq!(
+            hashes(|| {
+                if let Entry::Vacant(e) = v.entry(Counted(1001)) {
+                    let (_, v) = e.insert_key_value(1);
+                    *v += 2;
+                }
+            }),
+            1
+        );
+        // handing out an occupied entry hashes the new key once more
+        assert_eq!(
+            hashes(|| {
+                if let Entry::Vacant(e) = v.entry(Counted(1002)) {
+                    let mut o = e.insert_entry(1);
+                    *o.get_mut() += 1;
+                    o.insert(3);
+                }
+            }),
+            2
+        );
+        assert_eq!(
+            hashes(|| {
+                let _ = v.entry(Counted(3)).and_remove_if(|v| *v == 3);
+            }),
+            1
+        );
+        assert_eq!(v.capacity(), capacity);
+        assert_eq!(v.get(&Counted(1)), Some(&2));
+        assert_eq!(v.get(&Counted(2)), Some(&4));
+        assert_eq!(v.get(&Counted(1000)), Some(&7));
+        assert_eq!(v.get(&Counted(1001)), Some(&3));
+        assert_eq!(v.get(&Counted(1002)), Some(&3));
+        assert_eq!(v.get(&Counted(3)), None);
+    }
+
+    #[test]
+    fn hint_large() {
+        let mut v = HashMap::new();
+        v.hint_large();
+        assert!(v.is_map());
+        for i in 0..10 {
+            v.insert(i, i);
+        }
+        for i in 0..10 {
+            assert_eq!(v.remove(&i), Some(i));
+            assert!(v.is_map());
         }
+        assert!(v.is_empty());
+        assert!(v.is_map());
     }
-    #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        match &self.0 {
-            DrainInt::Map(m) => m.size_hint(),
-            DrainInt::Vec(m) => m.size_hint(),
+
+    #[test]
+    fn hint_large_after_fast_build() {
+        let mut v = HashMap::vec_with_capacity(2 * VEC_LIMIT_UPPER);
+        for i in 0..2 * VEC_LIMIT_UPPER {
+            v.insert_nocheck(i % VEC_LIMIT_UPPER, i);
         }
+        assert!(v.is_vec());
+        v.hint_large();
+        assert!(v.is_map());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER);
+        // the first value of a duplicated key wins, like on lookups before
+        assert!((0..VEC_LIMIT_UPPER).all(|i| v.get(&i) == Some(&i)));
+        assert!(v.capacity() >= 2 * VEC_LIMIT_UPPER);
+        // already map backed, hinting again changes nothing
+        v.hint_large();
+        assert!(v.is_map());
+        assert_eq!(v.len(), VEC_LIMIT_UPPER);
     }
-}
-#[cfg(test)]
-mod tests {
-    use super::*;
+
     #[test]
-    fn scale_up() {
+    fn reserve_large() {
         let mut v = HashMap::new();
+        v.insert(1, 1);
+        v.reserve(VEC_LIMIT_UPPER - 1);
         assert!(v.is_vec());
-        for i in 1..33 {
-            // 32 entries
-            v.insert(i, i);
-            assert!(v.is_vec());
+        v.reserve(VEC_LIMIT_UPPER);
+        assert!(v.is_map());
+        assert!(v.capacity() > VEC_LIMIT_UPPER);
+        assert_eq!(v.get(&1), Some(&1));
+    }
+
+    #[test]
+    fn checked_insert() {
+        let mut v = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            assert_eq!(v.checked_insert(i, i), Ok(None));
         }
-        v.insert(33, 33);
+        assert!(v.is_vec());
+        assert!(!v.1.maybe_duplicates());
+        assert_eq!(v.checked_insert(0, 42), Ok(Some(0)));
+        assert!(v.is_vec());
+        assert_eq!(v.checked_insert(VEC_LIMIT_UPPER, 0), Ok(None));
         assert!(v.is_map());
+        assert_eq!(v.checked_insert(0, 0), Ok(Some(42)));
+        assert_eq!(v.len(), VEC_LIMIT_UPPER + 1);
     }
 
     #[test]
-    fn str_key() {
-        let mut v: HashMap<String, u32> = HashMap::new();
-        v.insert("hello".to_owned(), 42);
-        assert_eq!(v["hello"], 42);
+    fn entry_index() {
+        let mut v = HashMap::new();
+        for i in 0..VEC_LIMIT_UPPER {
+            v.insert(i, i);
+        }
+        for (pos, (k, _)) in v.iter().enumerate() {
+            assert_eq!(pos, *k);
+        }
+        for i in 0..VEC_LIMIT_UPPER {
+            match v.entry(i) {
+                Entry::Occupied(o) => assert_eq!(o.index(), Some(i)),
+                Entry::Vacant(_) => unreachable!(),
+            }
+        }
+        v.insert(VEC_LIMIT_UPPER, VEC_LIMIT_UPPER);
+        assert!(v.is_map());
+        match v.entry(0) {
+            Entry::Occupied(o) => assert_eq!(o.index(), None),
+            Entry::Vacant(_) => unreachable!(),
+        }
     }
 
     #[test]
-    fn add_remove() {
+    fn vacant_insert_entry() {
         let mut v = HashMap::new();
+        for i in 0..10 {
+            match v.entry(i) {
+                Entry::Vacant(e) => {
+                    let mut o = e.insert_entry(i);
+                    assert_eq!(o.key(), &i);
+                    assert_eq!(o.insert(i + 1), i);
+                }
+                Entry::Occupied(_) => unreachable!(),
+            }
+            assert_eq!(v.get(&i), Some(&(i + 1)));
+        }
+        if let Entry::Vacant(e) = v.entry(10) {
+            assert_eq!(e.insert_entry(10).remove_entry(), (10, 10));
+        }
+        assert!(v.is_vec());
+        assert_eq!(v.len(), 10);
+
+        v.hint_large();
+        if let Entry::Vacant(e) = v.entry(10) {
+            let mut o = e.insert_entry(10);
+            assert_eq!(o.insert(11), 10);
+        }
+        assert_eq!(v.get(&10), Some(&11));
+        if let Entry::Vacant(e) = v.entry(11) {
+            assert_eq!(e.insert_entry(11).remove_entry(), (11, 11));
+        }
+        assert!(v.is_map());
+        assert_eq!(v.len(), 11);
+    }
+
+    #[test]
+    fn entry_ref() {
+        let mut v: HashMap<String, usize> = HashMap::new();
+        let words: Vec<String> = (0..40).map(|i| format!("word{i}")).collect();
+        for round in 0..2 {
+            for w in &words {
+                let mut created = false;
+                let len = v.entry_ref(w.as_str()).or_insert_with_key(|k| {
+                    created = true;
+                    k.len()
+                });
+                assert_eq!(*len, w.len() + round);
+                *len += 1;
+                assert_eq!(created, round == 0);
+            }
+        }
+        assert_eq!(v.len(), words.len());
+        assert_eq!(v["word1"], 7);
+        v.hint_large();
+        assert_eq!(*v.entry_ref("word1").or_insert(0), 7);
+        assert_eq!(*v.entry_ref("new").or_insert_with_key(str::len), 3);
+        match v.entry_ref("new") {
+            EntryRef::Occupied(o) => assert_eq!(o.remove_entry(), ("new".to_string(), 3)),
+            EntryRef::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(v.get("new"), None);
+    }
+
+    #[test]
+    fn checked_insert_overflow() {
+        // Actually running out of capacity in `checked_insert` isn't feasible
+        // so we check that both backends report overflows on reservation.
+        let mut v: HashMap<u64, u64> = HashMap::new();
         v.insert(1, 1);
-        v.insert(2, 2);
-        v.insert(3, 3);
-        assert_eq!(v.get(&1), Some(&1));
-        assert_eq!(v.get(&2), Some(&2));
-        assert_eq!(v.get(&3), Some(&3));
-        v.remove(&2);
+        if let HashMapInt::Vec(m) = &mut v.0 {
+            assert_eq!(
+                m.try_reserve(usize::MAX),
+                Err(TryReserveError::CapacityOverflow)
+            );
+        }
+        assert_eq!(
+            v.try_swap_to_map(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert!(v.is_vec());
         assert_eq!(v.get(&1), Some(&1));
-        assert_eq!(v.get(&2), None);
-        assert_eq!(v.get(&3), Some(&3));
     }
 }
@@ -0,0 +1,113 @@
+//! Keys for [`HashMap::lookup`], either a plain borrow or a borrow with
+//! its hash already computed.
+//!
+//! [`HashMap::lookup`]: struct.HashMap.html#method.lookup
+
+use std::fmt;
+
+/// A borrowed key together with its precomputed hash.
+///
+/// The hash has to be computed with the [`BuildHasher`] of the map it is
+/// used with, [`HashMap::hashed_key`] does exactly that. Looking up a key
+/// with a hash from a different hasher will not find it.
+///
+/// [`BuildHasher`]: https://doc.rust-lang.org/std/hash/trait.BuildHasher.html
+/// [`HashMap::hashed_key`]: struct.HashMap.html#method.hashed_key
+pub struct HashedKey<'q, Q: ?Sized> {
+    key: &'q Q,
+    hash: u64,
+}
+
+impl<'q, Q: ?Sized> HashedKey<'q, Q> {
+    /// Pairs `key` with a `hash` the caller computed for it.
+    #[inline]
+    #[must_use]
+    pub fn new(hash: u64, key: &'q Q) -> Self {
+        Self { key, hash }
+    }
+
+    /// Returns the borrowed key.
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> &'q Q {
+        self.key
+    }
+
+    /// Returns the precomputed hash.
+    #[inline]
+    #[must_use]
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl<Q: ?Sized> Clone for HashedKey<'_, Q> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Q: ?Sized> Copy for HashedKey<'_, Q> {}
+
+impl<Q: ?Sized + fmt::Debug> fmt::Debug for HashedKey<'_, Q> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashedKey")
+            .field("key", &self.key)
+            .field("hash", &self.hash)
+            .finish()
+    }
+}
+
+/// A key to look up with [`HashMap::lookup`].
+///
+/// Both `&Q` and [`HashedKey`] convert into it, so callers rarely need to
+/// name it.
+///
+/// [`HashMap::lookup`]: struct.HashMap.html#method.lookup
+pub enum LookupKey<'q, Q: ?Sized> {
+    /// A borrowed key, hashed on lookup if the map needs it.
+    Borrowed(&'q Q),
+    /// A borrowed key with its hash already computed.
+    Hashed(HashedKey<'q, Q>),
+}
+
+impl<'q, Q: ?Sized> LookupKey<'q, Q> {
+    /// Returns the borrowed key.
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> &'q Q {
+        match self {
+            LookupKey::Borrowed(key) => key,
+            LookupKey::Hashed(hashed) => hashed.key(),
+        }
+    }
+}
+
+impl<Q: ?Sized> Clone for LookupKey<'_, Q> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Q: ?Sized> Copy for LookupKey<'_, Q> {}
+
+impl<Q: ?Sized + fmt::Debug> fmt::Debug for LookupKey<'_, Q> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupKey::Borrowed(key) => f.debug_tuple("Borrowed").field(key).finish(),
+            LookupKey::Hashed(hashed) => f.debug_tuple("Hashed").field(hashed).finish(),
+        }
+    }
+}
+
+impl<'q, Q: ?Sized> From<&'q Q> for LookupKey<'q, Q> {
+    fn from(key: &'q Q) -> Self {
+        LookupKey::Borrowed(key)
+    }
+}
+
+impl<'q, Q: ?Sized> From<HashedKey<'q, Q>> for LookupKey<'q, Q> {
+    fn from(hashed: HashedKey<'q, Q>) -> Self {
+        LookupKey::Hashed(hashed)
+    }
+}
@@ -0,0 +1,46 @@
+//! Process wide counters of backend changes, to see how often maps cross
+//! the vector limit across a whole program.
+//!
+//! Only compiled with the `metrics` feature, without it the backend changes
+//! aren't counted at all.
+//!
+//! ```
+//! use halfbrown::HashMap;
+//!
+//! let before = halfbrown::metrics::promotions();
+//! let mut map = HashMap::new();
+//! for i in 0..100 {
+//!     map.insert(i, i);
+//! }
+//! assert!(halfbrown::metrics::promotions() > before);
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static PROMOTIONS: AtomicU64 = AtomicU64::new(0);
+static DEMOTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns how many maps moved from the vector to the map backend so far.
+#[inline]
+#[must_use]
+pub fn promotions() -> u64 {
+    PROMOTIONS.load(Ordering::Relaxed)
+}
+
+/// Returns how many maps moved from the map back to the vector backend so
+/// far.
+#[inline]
+#[must_use]
+pub fn demotions() -> u64 {
+    DEMOTIONS.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub(crate) fn promoted() {
+    PROMOTIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline]
+pub(crate) fn demoted() {
+    DEMOTIONS.fetch_add(1, Ordering::Relaxed);
+}
@@ -1,7 +1,7 @@
 // based on / take from <https://github.com/rust-lang/hashbrown/blob/62a1ae24d4678fcbf777bef6b205fadeecb781d9/src/map.rs>
 
 use super::{fmt, hashbrown, Borrow, BuildHasher, Debug, Hash};
-use crate::vecmap;
+use crate::{vecmap, HashMap, HashMapInt};
 use hashbrown::hash_map;
 /*
 use std::fmt::{self, Debug};
@@ -32,9 +32,81 @@ where
         Self(RawEntryBuilderMutInt::Vec(m))
     }
 }
+impl<'map, K, V, S> RawEntryBuilderMut<'map, K, V, S> {
+    /// Creates a builder for a full vector backed map, the map is promoted
+    /// once a value is inserted into a vacant entry.
+    pub(crate) fn promoting(map: &'map mut HashMap<K, V, S>) -> Self
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        Self(RawEntryBuilderMutInt::Promote {
+            map,
+            promote: |map| map.swap_to_map(1),
+        })
+    }
+}
+
+/// Promotes a vector backed map, the raw entry methods don't require the
+/// bounds promotion needs so we capture it when creating the builder.
+type Promote<K, V, S> = fn(&mut HashMap<K, V, S>);
+
 enum RawEntryBuilderMutInt<'map, K, V, S> {
     Vec(vecmap::RawEntryBuilderMut<'map, K, V, S>),
     Map(hash_map::RawEntryBuilderMut<'map, K, V, S>),
+    /// a full vector backed map
+    Promote {
+        map: &'map mut HashMap<K, V, S>,
+        promote: Promote<K, V, S>,
+    },
+}
+
+/// Searches a full vector backed map, handing out an entry that promotes
+/// the map on insertion if no key matches.
+fn search_promoting<K, V, S, F>(
+    map: &mut HashMap<K, V, S>,
+    promote: Promote<K, V, S>,
+    is_match: F,
+) -> RawEntryMut<'_, K, V, S>
+where
+    F: FnMut(&K) -> bool,
+{
+    let idx = match &map.0 {
+        HashMapInt::Vec(m) => m.position(is_match),
+        HashMapInt::Map(_) => unreachable!(),
+        HashMapInt::None => none_state!(),
+    };
+    match idx {
+        Some(idx) => match &mut map.0 {
+            HashMapInt::Vec(m) => {
+                RawEntryMut::Occupied(vecmap::RawOccupiedEntryMut::new(idx, m).into())
+            }
+            HashMapInt::Map(_) => unreachable!(),
+            HashMapInt::None => none_state!(),
+        },
+        None => RawEntryMut::Vacant(RawVacantEntryMut(RawVacantEntryMutInt::Promote {
+            map,
+            promote,
+        })),
+    }
+}
+
+/// Promotes the map and returns a vacant entry of the new map backend.
+fn promoted<K, V, S>(
+    map: &mut HashMap<K, V, S>,
+    promote: Promote<K, V, S>,
+) -> hash_map::RawVacantEntryMut<'_, K, V, S> {
+    promote(map);
+    match &mut map.0 {
+        // the hash doesn't matter as nothing matches, inserting into the
+        // entry computes the hash of the key
+        HashMapInt::Map(m) => match m.raw_entry_mut().from_hash(0, |_| false) {
+            hash_map::RawEntryMut::Vacant(e) => e,
+            hash_map::RawEntryMut::Occupied(_) => unreachable!(),
+        },
+        HashMapInt::Vec(_) => unreachable!(),
+        HashMapInt::None => none_state!(),
+    }
 }
 
 /// A view into a single entry in a map, which may either be vacant or occupied.
@@ -87,7 +159,6 @@ impl<'map, K, V, S> From<vecmap::RawOccupiedEntryMut<'map, K, V, S>>
     }
 }
 
-
 impl<'map, K, V, S> From<hash_map::RawOccupiedEntryMut<'map, K, V, S>>
     for RawOccupiedEntryMut<'map, K, V, S>
 {
@@ -112,7 +183,6 @@ where
 {
 }
 
-
 enum RawOccupiedEntryMutInt<'map, K, V, S> {
     Vec(vecmap::RawOccupiedEntryMut<'map, K, V, S>),
     Map(hash_map::RawOccupiedEntryMut<'map, K, V, S>),
@@ -158,6 +228,10 @@ impl<'map, K, V, S> From<hash_map::RawVacantEntryMut<'map, K, V, S>>
 enum RawVacantEntryMutInt<'map, K, V, S> {
     Vec(vecmap::RawVacantEntryMut<'map, K, V, S>),
     Map(hash_map::RawVacantEntryMut<'map, K, V, S>),
+    Promote {
+        map: &'map mut HashMap<K, V, S>,
+        promote: Promote<K, V, S>,
+    },
 }
 
 /// A builder for computing where in a [`HashMap`] a key-value pair would be stored.
@@ -204,6 +278,9 @@ where
         match self.0 {
             RawEntryBuilderMutInt::Vec(m) => m.from_key(k).into(),
             RawEntryBuilderMutInt::Map(m) => m.from_key(k).into(),
+            RawEntryBuilderMutInt::Promote { map, promote } => {
+                search_promoting(map, promote, |q| q.borrow().eq(k))
+            }
         }
     }
 
@@ -218,6 +295,9 @@ where
         match self.0 {
             RawEntryBuilderMutInt::Vec(m) => m.from_key_hashed_nocheck(hash, k).into(),
             RawEntryBuilderMutInt::Map(m) => m.from_key_hashed_nocheck(hash, k).into(),
+            RawEntryBuilderMutInt::Promote { map, promote } => {
+                search_promoting(map, promote, |q| q.borrow().eq(k))
+            }
         }
     }
 }
@@ -236,6 +316,9 @@ where
         match self.0 {
             RawEntryBuilderMutInt::Vec(m) => m.from_hash(hash, is_match).into(),
             RawEntryBuilderMutInt::Map(m) => m.from_hash(hash, is_match).into(),
+            RawEntryBuilderMutInt::Promote { map, promote } => {
+                search_promoting(map, promote, is_match)
+            }
         }
     }
 }
@@ -258,6 +341,50 @@ where
         }
     }
 
+    /// Access an entry by key, also returning the position it was found at.
+    ///
+    /// For vector backed maps this is the index of the entry in the vector,
+    /// it stays valid until the map is changed. Map backed maps have no
+    /// stable position for their entries, for them the index is `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    ///
+    /// let mut map: HashMap<&str, u32> = HashMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// assert_eq!(
+    ///     map.raw_entry().from_key_returning_index("b"),
+    ///     Some((Some(1), &"b", &2))
+    /// );
+    ///
+    /// map.hint_large();
+    /// assert_eq!(
+    ///     map.raw_entry().from_key_returning_index("b"),
+    ///     Some((None, &"b", &2))
+    /// );
+    /// assert_eq!(map.raw_entry().from_key_returning_index("c"), None);
+    /// ```
+    #[inline]
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_key_returning_index<Q: ?Sized>(
+        self,
+        k: &Q,
+    ) -> Option<(Option<usize>, &'map K, &'map V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        match self.0 {
+            RawEntryBuilderInt::Vec(m) => m
+                .from_key_returning_index(k)
+                .map(|(idx, k, v)| (Some(idx), k, v)),
+            RawEntryBuilderInt::Map(m) => m.from_key(k).map(|(k, v)| (None, k, v)),
+        }
+    }
+
     /// Access an entry by a key and its hash.
     #[inline]
     #[allow(clippy::wrong_self_convention)]
@@ -322,6 +449,11 @@ where
                     RawVacantEntryMutInt::Map(e) => {
                         hash_map::RawEntryMut::Vacant(e).insert(key, value).into()
                     }
+                    RawVacantEntryMutInt::Promote { map, promote } => {
+                        hash_map::RawEntryMut::Vacant(promoted(map, promote))
+                            .insert(key, value)
+                            .into()
+                    }
                 }
             }
         }
@@ -410,6 +542,7 @@ where
     /// assert_eq!(map["poneyland"], 43);
     /// ```
     #[inline]
+    #[must_use]
     pub fn and_modify<F>(self, f: F) -> Self
     where
         F: FnOnce(&mut K, &mut V),
@@ -574,6 +707,9 @@ where
         match self.0 {
             RawVacantEntryMutInt::Vec(e) => e.insert(key, value),
             RawVacantEntryMutInt::Map(e) => e.insert(key, value),
+            RawVacantEntryMutInt::Promote { map, promote } => {
+                promoted(map, promote).insert(key, value)
+            }
         }
     }
 
@@ -589,6 +725,9 @@ where
         match self.0 {
             RawVacantEntryMutInt::Vec(e) => e.insert_hashed_nocheck(hash, key, value),
             RawVacantEntryMutInt::Map(e) => e.insert_hashed_nocheck(hash, key, value),
+            RawVacantEntryMutInt::Promote { map, promote } => {
+                promoted(map, promote).insert_hashed_nocheck(hash, key, value)
+            }
         }
     }
 
@@ -608,6 +747,9 @@ where
         match self.0 {
             RawVacantEntryMutInt::Vec(e) => e.insert_with_hasher(hash, key, value, hasher),
             RawVacantEntryMutInt::Map(e) => e.insert_with_hasher(hash, key, value, hasher),
+            RawVacantEntryMutInt::Promote { map, promote } => {
+                promoted(map, promote).insert_with_hasher(hash, key, value, hasher)
+            }
         }
     }
 }
@@ -0,0 +1,163 @@
+use crate::{HashMap, HashMapInt};
+use core::hash::{BuildHasher, Hash};
+use hashbrown::hash_map::rayon as hb;
+use rayon::iter::plumbing::UnindexedConsumer;
+use rayon::iter::{
+    Either, FromParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
+    IntoParallelRefMutIterator, ParallelIterator,
+};
+
+/// Parallel iterator over the key value pairs of a Halfbrown map
+pub struct ParIter<'a, K, V>(ParIterInt<'a, K, V>);
+
+enum ParIterInt<'a, K, V> {
+    Map(hb::ParIter<'a, K, V>),
+    Vec(rayon::slice::Iter<'a, (K, V)>),
+}
+
+impl<'a, K: Sync, V: Sync> ParallelIterator for ParIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        match self.0 {
+            ParIterInt::Map(i) => i.drive_unindexed(consumer),
+            ParIterInt::Vec(i) => i.map(|(k, v)| (k, v)).drive_unindexed(consumer),
+        }
+    }
+}
+
+/// Mutable parallel iterator over the key value pairs of a Halfbrown map
+pub struct ParIterMut<'a, K, V>(ParIterMutInt<'a, K, V>);
+
+enum ParIterMutInt<'a, K, V> {
+    Map(hb::ParIterMut<'a, K, V>),
+    Vec(rayon::slice::IterMut<'a, (K, V)>),
+}
+
+impl<'a, K: Sync + Send, V: Send> ParallelIterator for ParIterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        match self.0 {
+            ParIterMutInt::Map(i) => i.drive_unindexed(consumer),
+            ParIterMutInt::Vec(i) => i.map(|(k, v)| (&*k, v)).drive_unindexed(consumer),
+        }
+    }
+}
+
+/// Makes `par_iter` available on a `HashMap`, yielding the same pairs as
+/// `iter` in arbitrary order.
+///
+/// # Examples
+///
+/// ```
+/// use halfbrown::HashMap;
+/// use rayon::prelude::*;
+///
+/// let map: HashMap<usize, usize> = (0..100).map(|i| (i, i)).collect();
+/// assert_eq!(map.par_iter().map(|(k, v)| k + v).sum::<usize>(), 9900);
+/// ```
+impl<'a, K: Sync, V: Sync, S> IntoParallelIterator for &'a HashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type Iter = ParIter<'a, K, V>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter(match &self.0 {
+            HashMapInt::Map(m) => ParIterInt::Map(m.par_iter()),
+            HashMapInt::Vec(m) => ParIterInt::Vec(m.as_vec().par_iter()),
+            HashMapInt::None => none_state!(),
+        })
+    }
+}
+
+/// Makes `par_iter_mut` available on a `HashMap`, yielding the same pairs
+/// as `iter_mut` in arbitrary order.
+///
+/// # Examples
+///
+/// ```
+/// use halfbrown::HashMap;
+/// use rayon::prelude::*;
+///
+/// let mut map: HashMap<usize, usize> = (0..10).map(|i| (i, i)).collect();
+/// map.par_iter_mut().for_each(|(k, v)| *v += k);
+/// assert_eq!(map[&7], 14);
+/// ```
+impl<'a, K: Sync + Send, V: Send, S> IntoParallelIterator for &'a mut HashMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type Iter = ParIterMut<'a, K, V>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIterMut(match &mut self.0 {
+            HashMapInt::Map(m) => ParIterMutInt::Map(m.par_iter_mut()),
+            HashMapInt::Vec(m) => ParIterMutInt::Vec(m.as_mut_slice().par_iter_mut()),
+            HashMapInt::None => none_state!(),
+        })
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    /// A parallel iterator visiting all values mutably in arbitrary order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use halfbrown::HashMap;
+    /// use rayon::prelude::*;
+    ///
+    /// let mut map: HashMap<usize, usize> = (0..100).map(|i| (i, i)).collect();
+    /// map.par_values_mut().for_each(|v| *v *= 2);
+    /// assert_eq!(map[&21], 42);
+    /// ```
+    pub fn par_values_mut(&mut self) -> impl ParallelIterator<Item = &mut V>
+    where
+        K: Send + Sync,
+        V: Send,
+    {
+        match &mut self.0 {
+            HashMapInt::Map(m) => Either::Left(m.par_values_mut()),
+            HashMapInt::Vec(m) => Either::Right(m.as_mut_slice().par_iter_mut().map(|(_, v)| v)),
+            HashMapInt::None => none_state!(),
+        }
+    }
+}
+
+/// Collects the pairs of a parallel iterator into a `HashMap`.
+///
+/// Each thread gathers its pairs into a vector of its own, the vectors are
+/// then merged into a map that stays vector backed if all pairs fit into a
+/// vector.
+///
+/// If a key shows up more than once the value that comes last in the order
+/// of the parallel iterator wins. For iterators without a defined order,
+/// like those over a hash set, which value that is isn't specified.
+impl<K, V, S> FromParallelIterator<(K, V)> for HashMap<K, V, S>
+where
+    K: Eq + Hash + Send,
+    V: Send,
+    S: BuildHasher + Default,
+{
+    fn from_par_iter<I>(par_iter: I) -> Self
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        let chunks: Vec<Vec<(K, V)>> = par_iter
+            .into_par_iter()
+            .fold(Vec::new, |mut chunk, pair| {
+                chunk.push(pair);
+                chunk
+            })
+            .collect();
+        let mut map = Self::presized(chunks.iter().map(Vec::len).sum(), S::default());
+        for (k, v) in chunks.into_iter().flatten() {
+            map.insert(k, v);
+        }
+        map
+    }
+}
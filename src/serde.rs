@@ -1,8 +1,12 @@
 mod se {
-    use crate::HashMap;
+    use crate::{HashMap, HashMapInt};
     use core::hash::Hash;
     use serde::ser::{Serialize, SerializeMap, Serializer};
 
+    /// Vector backed maps serialize their entries in the order the vector
+    /// holds them, which is insertion order as long as nothing was removed,
+    /// so small maps give deterministic output. Map backed maps serialize in
+    /// the unspecified order of the hash table.
     impl<K, V> Serialize for HashMap<K, V>
     where
         K: Eq + Hash + Serialize,
@@ -12,9 +16,21 @@ mod se {
         where
             S: Serializer,
         {
+            // `len` is cheap for both backends, so formats always get to
+            // know the number of entries up front.
             let mut map = serializer.serialize_map(Some(self.len()))?;
-            for (k, v) in self.iter() {
-                map.serialize_entry(k, v)?;
+            match &self.0 {
+                HashMapInt::Vec(m) => {
+                    for (k, v) in m.iter() {
+                        map.serialize_entry(k, v)?;
+                    }
+                }
+                HashMapInt::Map(m) => {
+                    for (k, v) in m {
+                        map.serialize_entry(k, v)?;
+                    }
+                }
+                HashMapInt::None => none_state!(),
             }
             map.end()
         }
@@ -22,18 +38,22 @@ mod se {
 }
 
 mod de {
-    use crate::HashMap;
+    use crate::{DefaultHashBuilder, HashMap};
     use core::hash::Hash;
     use core::marker::PhantomData;
     use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
     use std::fmt;
 
+    /// Unused capacity that is fine to keep after deserializing, below this
+    /// it isn't worth reallocating.
+    const SHRINK_SLACK: usize = 16;
+
     mod size_hint {
         use core::cmp;
 
         /// This presumably exists to prevent denial of service attacks.
         ///
-        /// Original discussion: https://github.com/serde-rs/serde/issues/1114.
+        /// Original discussion: <https://github.com/serde-rs/serde/issues/1114>.
         #[inline]
         pub(super) fn cautious(hint: Option<usize>) -> usize {
             cmp::min(hint.unwrap_or(0), 4096)
@@ -79,11 +99,19 @@ mod de {
         {
             let size = size_hint::cautious(map.size_hint());
 
-            let mut m = HashMap::with_capacity(size);
+            // Pick the backend from the hint so large maps are never built
+            // in a vector first. Formats without a hint, like JSON, start
+            // out with a vector and promote once they pass the limit.
+            let mut m = HashMap::presized(size, DefaultHashBuilder::default());
             while let Some(k) = map.next_key()? {
                 let v = map.next_value()?;
                 m.insert(k, v);
             }
+            // formats may hint at far more entries than they contain, don't
+            // keep the memory reserved for them around
+            if m.capacity() > m.len().saturating_mul(2).max(SHRINK_SLACK) {
+                m.shrink_to_fit();
+            }
             Ok(m)
         }
     }
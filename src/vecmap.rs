@@ -1,11 +1,12 @@
-///! A vector based map data structure, mostly for internal use
+//! A vector based map data structure, mostly for internal use
 mod entry;
 mod iter;
 mod raw_entry;
 
 pub(crate) use self::entry::*;
 pub(crate) use self::raw_entry::*;
-use crate::DefaultHashBuilder;
+use crate::{DefaultHashBuilder, TryReserveError};
+use std::alloc::Layout;
 use std::borrow::Borrow;
 
 #[derive(Debug, Clone)]
@@ -37,7 +38,7 @@ where
         }
 
         self.iter()
-            .all(|(key, value)| other.get(key).map_or(false, |v| value == v.borrow()))
+            .all(|(key, value)| other.get(key).is_some_and(|v| value == v.borrow()))
     }
 }
 
@@ -57,17 +58,102 @@ impl<K, V> VecMap<K, V, DefaultHashBuilder> {
 }
 
 impl<K, V, S> VecMap<K, V, S> {
+    #[inline]
+    pub(crate) fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            v: Vec::with_capacity(capacity),
+            hash_builder,
+        }
+    }
+
     #[inline]
     pub fn retain<F>(&mut self, mut f: F)
     where
         F: FnMut(&K, &mut V) -> bool,
     {
-        let mut new = Vec::new();
-        std::mem::swap(&mut new, &mut self.v);
-        self.v = new
-            .into_iter()
-            .filter_map(|(k, mut v)| if f(&k, &mut v) { Some((k, v)) } else { None })
-            .collect();
+        // compacts in a single pass and keeps the allocation
+        self.v.retain_mut(|(k, v)| f(k, v));
+    }
+
+    /// Removes all elements `pred` returns `true` for in a single pass and
+    /// returns them, the order of the remaining ones isn't kept.
+    #[inline]
+    pub(crate) fn extract<F>(&mut self, mut pred: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut extracted = Vec::new();
+        let mut i = 0;
+        while let Some((k, v)) = self.v.get(i) {
+            if pred(k, v) {
+                extracted.push(self.v.swap_remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        extracted
+    }
+
+    /// Returns the position of the first key `is_match` returns `true` for.
+    #[inline]
+    pub(crate) fn position<F>(&self, mut is_match: F) -> Option<usize>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.v.iter().position(|(k, _)| is_match(k))
+    }
+
+    /// Borrows the pairs at the positions in `idxs` mutably at once,
+    /// returning `None` if a position is out of bounds or repeated.
+    pub(crate) fn get_many_at_mut<const N: usize>(
+        &mut self,
+        idxs: [usize; N],
+    ) -> Option<[&mut (K, V); N]> {
+        let mut order = [0; N];
+        for (i, o) in order.iter_mut().enumerate() {
+            *o = i;
+        }
+        order.sort_unstable_by_key(|i| idxs[*i]);
+        let mut found: [Option<&mut (K, V)>; N] = [(); N].map(|()| None);
+        let mut rest = &mut self.v[..];
+        let mut offset = 0;
+        for i in order {
+            // sorted, so a repeated position lands before `offset`
+            let skip = idxs[i].checked_sub(offset)?;
+            let (kv, tail) = std::mem::take(&mut rest)
+                .get_mut(skip..)?
+                .split_first_mut()?;
+            found[i] = Some(kv);
+            rest = tail;
+            offset = idxs[i] + 1;
+        }
+        Some(found.map(|kv| match kv {
+            Some(kv) => kv,
+            None => unreachable!(),
+        }))
+    }
+
+    #[inline]
+    pub(crate) fn as_vec(&self) -> &Vec<(K, V)> {
+        &self.v
+    }
+
+    /// Changing the keys through this breaks the map, it's only meant for
+    /// getting at the values.
+    #[cfg(feature = "rayon")]
+    #[inline]
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [(K, V)] {
+        &mut self.v
+    }
+
+    #[inline]
+    pub(crate) fn from_parts(v: Vec<(K, V)>, hash_builder: S) -> Self {
+        Self { v, hash_builder }
+    }
+
+    #[inline]
+    pub(crate) fn into_parts(self) -> (Vec<(K, V)>, S) {
+        (self.v, self.hash_builder)
     }
 
     #[inline]
@@ -95,8 +181,10 @@ impl<K, V, S> VecMap<K, V, S> {
         self.v.is_empty()
     }
 
+    /// `HashMap::drain` promises the pairs come out in the order they are
+    /// stored in, so this must not reorder them.
     #[inline]
-    pub(crate) fn drain(&mut self) -> std::vec::Drain<(K, V)> {
+    pub(crate) fn drain(&mut self) -> std::vec::Drain<'_, (K, V)> {
         self.v.drain(..)
     }
 
@@ -105,11 +193,30 @@ impl<K, V, S> VecMap<K, V, S> {
         self.v.reserve(additional);
     }
 
+    #[inline]
+    pub(crate) fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.v.try_reserve(additional).map_err(|_| {
+            // std doesn't let us look into its error so we recompute the
+            // layout to tell an overflow and a failed allocation apart
+            self.v
+                .len()
+                .checked_add(additional)
+                .and_then(|capacity| Layout::array::<(K, V)>(capacity).ok())
+                .map_or(TryReserveError::CapacityOverflow, |layout| {
+                    TryReserveError::AllocError { layout }
+                })
+        })
+    }
+
     #[inline]
     pub(crate) fn shrink_to_fit(&mut self) {
         self.v.shrink_to_fit();
     }
     #[inline]
+    pub(crate) fn shrink_to(&mut self, min_capacity: usize) {
+        self.v.shrink_to(min_capacity);
+    }
+    #[inline]
     pub(crate) fn clear(&mut self) {
         self.v.clear();
     }
@@ -136,6 +243,16 @@ impl<K, V, S> VecMap<K, V, S> {
 
     #[inline]
     pub(crate) fn remove<Q: ?Sized>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+    {
+        self.remove_entry(k).map(|(_, v)| v)
+    }
+
+    /// Removes the pair stored for `k`, the last element takes its place.
+    #[inline]
+    pub(crate) fn remove_entry<Q: ?Sized>(&mut self, k: &Q) -> Option<(K, V)>
     where
         K: Borrow<Q>,
         Q: Eq,
@@ -145,7 +262,7 @@ impl<K, V, S> VecMap<K, V, S> {
             let (ak, _) = unsafe { self.v.get_unchecked(i) };
             if k == ak.borrow() {
                 unsafe {
-                    return Some(self.remove_idx(i).1);
+                    return Some(self.remove_idx(i));
                 }
             }
             i += 1;
@@ -158,7 +275,23 @@ impl<K, V, S> VecMap<K, V, S> {
         self.v.push((k, v));
     }
 
-    pub(crate) fn entry(&mut self, key: K) -> Entry<K, V, S>
+    /// Removes all but the first pair of every key, the ones lookups find,
+    /// keeping the order of the remaining pairs.
+    pub(crate) fn dedup(&mut self)
+    where
+        K: Eq,
+    {
+        let mut i = 1;
+        while i < self.v.len() {
+            if self.v[..i].iter().any(|(k, _)| *k == self.v[i].0) {
+                self.v.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    pub(crate) fn entry(&mut self, key: K) -> Entry<'_, K, V, S>
     where
         K: Eq,
     {
@@ -211,6 +344,20 @@ impl<K, V, S> VecMap<K, V, S> {
         None
     }
 
+    #[inline]
+    pub(crate) fn get_key_value<Q: ?Sized>(&self, k: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+    {
+        for (ak, v) in &self.v {
+            if k.eq((*ak).borrow()) {
+                return Some((ak, v));
+            }
+        }
+        None
+    }
+
     /// Creates a raw entry builder for the `HashMap`.
     ///
     /// Raw entries provide the lowest level of control for searching and
@@ -182,6 +182,11 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
         }
     }
 
+    /// The number of elements in the map the entry points into.
+    pub(crate) fn map_len(&self) -> usize {
+        self.map.len()
+    }
+
     /// Gets a reference to the key in the entry.
     ///
     /// # Examples
@@ -198,6 +203,12 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
         unsafe { &self.map.v.get_unchecked(self.idx).0 }
     }
 
+    /// Gets the position of the entry in the underlying vector.
+    #[inline]
+    pub(crate) fn index(&self) -> usize {
+        self.idx
+    }
+
     /// Take the ownership of the key and value from the map.
     ///
     /// # Examples
@@ -273,6 +284,38 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
         unsafe { &mut self.map.v.get_unchecked_mut(self.idx).1 }
     }
 
+    /// Gets a reference to the key and a mutable reference to the value in
+    /// the entry.
+    #[inline]
+    pub(crate) fn key_value_mut(&mut self) -> (&K, &mut V) {
+        let (k, v) = unsafe { self.map.v.get_unchecked_mut(self.idx) };
+        (k, v)
+    }
+
+    /// Converts the entry into a reference to the key and a mutable
+    /// reference to the value with a lifetime bound to the map itself.
+    #[inline]
+    pub(crate) fn into_key_value(self) -> (&'a K, &'a mut V) {
+        let (k, v) = &mut self.map.v[self.idx];
+        (k, v)
+    }
+
+    /// Replaces the value with the one `f` computes from it and returns a
+    /// reference to the new value. If `f` panics the entry is removed.
+    #[inline]
+    pub(crate) fn replace_with<F>(self, f: F) -> &'a mut V
+    where
+        F: FnOnce(V) -> V,
+    {
+        let v = &mut self.map.v;
+        let (k, old) = v.swap_remove(self.idx);
+        let new = f(old);
+        v.push((k, new));
+        let last = v.len() - 1;
+        v.swap(self.idx, last);
+        &mut v[self.idx].1
+    }
+
     /// Converts the `OccupiedEntry` into a mutable reference to the value in the entry
     /// with a lifetime bound to the map itself.
     ///
@@ -378,6 +421,21 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
         (old_key, old_value)
     }
 
+    /// Removes the entry if `pred` returns `true` for its value, handing
+    /// back a vacant entry for the stored key in that case.
+    #[inline]
+    pub(crate) fn remove_if<F>(mut self, pred: F) -> Entry<'a, K, V, S>
+    where
+        F: FnOnce(&mut V) -> bool,
+    {
+        if pred(self.get_mut()) {
+            let (key, _value) = unsafe { self.map.remove_idx(self.idx) };
+            Entry::Vacant(VacantEntry::new(key, self.map))
+        } else {
+            Entry::Occupied(self)
+        }
+    }
+
     /// Replaces the key in the hash map with the key used to create this entry.
     ///
     /// # Examples
@@ -429,6 +487,11 @@ impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
     pub(crate) fn new(key: K, map: &'a mut VecMap<K, V, S>) -> Self {
         Self { key, map }
     }
+
+    /// The number of elements in the map the entry points into.
+    pub(crate) fn map_len(&self) -> usize {
+        self.map.len()
+    }
     /// Gets a reference to the key that would be used when inserting a value
     /// through the `VacantEntry`.
     ///
@@ -488,4 +551,19 @@ impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
         let i = self.map.insert_idx(self.key, value);
         unsafe { &mut self.map.v.get_unchecked_mut(i).1 }
     }
+
+    /// Sets the value of the entry with the `VacantEntry`'s key,
+    /// and returns an `OccupiedEntry`.
+    ///
+    /// The entry points straight at the newly pushed slot so the key
+    /// doesn't have to be looked up again.
+    #[inline]
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S> {
+        let idx = self.map.insert_idx(self.key, value);
+        OccupiedEntry {
+            idx,
+            key: None,
+            map: self.map,
+        }
+    }
 }
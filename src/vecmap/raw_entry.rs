@@ -147,6 +147,19 @@ impl<'a, K, V, S> RawEntryBuilder<'a, K, V, S> {
         self.from_hash(hash, |q| q.borrow().eq(k))
     }
 
+    /// Access an entry by key, also returning its position in the vector.
+    #[inline]
+    #[allow(clippy::wrong_self_convention)]
+    pub(crate) fn from_key_returning_index<Q: ?Sized>(self, k: &Q) -> Option<(usize, &'a K, &'a V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+    {
+        let idx = self.map.position(|q| q.borrow().eq(k))?;
+        let (k, v) = &self.map.v[idx];
+        Some((idx, k, v))
+    }
+
     #[inline]
     fn search<F>(self, _hash: u64, mut is_match: F) -> Option<(&'a K, &'a V)>
     where
@@ -290,6 +303,10 @@ impl<'a, K, V, S> RawEntryMut<'a, K, V, S> {
 }
 
 impl<'a, K, V, S> RawOccupiedEntryMut<'a, K, V, S> {
+    pub(crate) fn new(idx: usize, map: &'a mut VecMap<K, V, S>) -> Self {
+        Self { idx, map }
+    }
+
     /// Gets a reference to the key in the entry.
     #[inline]
     pub fn key(&self) -> &K {
@@ -0,0 +1,168 @@
+//! Tests counting the allocations made by the map.
+
+use halfbrown::HashMap;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+}
+
+#[allow(clippy::cast_possible_wrap)]
+fn track(freed: usize, allocated: usize) {
+    LIVE_BYTES.with(|l| l.set(l.get() - freed as isize + allocated as isize));
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        track(0, layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        track(layout.size(), 0);
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        track(layout.size(), new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Returns the number of allocations the current thread made while running `f`.
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+/// Returns the number of bytes the current thread has allocated and not
+/// freed yet.
+fn live_bytes() -> isize {
+    LIVE_BYTES.with(Cell::get)
+}
+
+#[test]
+fn collect_into_reuses_allocation() {
+    for n in [16, 100] {
+        let mut map = HashMap::new();
+        assert!(allocations(|| map.collect_into((0..n).map(|i| (i, i)))) > 0);
+        assert_eq!(map.len(), n);
+
+        let reused = allocations(|| map.collect_into((n..2 * n).map(|i| (i, i))));
+        assert_eq!(reused, 0);
+        assert_eq!(map.len(), n);
+        assert_eq!(map.get(&n), Some(&n));
+        assert_eq!(map.get(&0), None);
+    }
+}
+
+#[test]
+fn entry_ref_allocates_only_on_insert() {
+    for n in [16, 100] {
+        let keys: Vec<String> = (0..n).map(|i| i.to_string()).collect();
+        let mut map: HashMap<String, usize> = HashMap::new();
+        for k in &keys {
+            map.insert(k.clone(), 0);
+        }
+
+        let hits = allocations(|| {
+            for i in 0..1000 {
+                *map.entry_ref(keys[i % n].as_str()).or_insert(0) += 1;
+            }
+        });
+        assert_eq!(hits, 0);
+
+        // looking up by an owned key allocates it even when it's present
+        let owned = allocations(|| {
+            for i in 0..1000 {
+                *map.entry(keys[i % n].to_owned()).or_insert(0) += 1;
+            }
+        });
+        assert!(owned >= 1000);
+        assert_eq!(map.values().sum::<usize>(), 2000);
+
+        // the key is only allocated once the entry is vacant
+        map.reserve(1);
+        let misses = allocations(|| {
+            map.entry_ref("absent").or_insert(0);
+        });
+        assert_eq!(misses, 1);
+        assert_eq!(map.len(), n + 1);
+    }
+}
+
+#[test]
+fn and_modify_hit_does_not_allocate() {
+    for n in [4, halfbrown::VEC_LIMIT_UPPER] {
+        let mut map = HashMap::new();
+        for i in 0..n {
+            map.insert(i, i);
+        }
+        assert!(map.is_vec());
+
+        let hits = allocations(|| {
+            for i in 0..1000 {
+                map.entry(i % n).and_modify(|v| *v += 1).or_insert(0);
+            }
+        });
+        assert_eq!(hits, 0);
+        assert!(map.is_vec());
+        assert_eq!(map.values().sum::<usize>(), (0..n).sum::<usize>() + 1000);
+    }
+}
+
+#[test]
+fn into_iter_frees_on_drop() {
+    const VALUE_LEN: isize = 64;
+    for n in [16, 1000] {
+        let base = live_bytes();
+        let map: HashMap<usize, String> = (0..n).map(|i| (i, "x".repeat(64))).collect();
+        let full = live_bytes() - base;
+
+        // pairs handed out are the caller's to free, the backing storage
+        // and the pairs not yet yielded stay alive with the iterator
+        let mut iter = map.into_iter();
+        for _ in 0..n / 2 {
+            drop(iter.next());
+        }
+        #[allow(clippy::cast_possible_wrap)]
+        let consumed = (n / 2) as isize * VALUE_LEN;
+        assert_eq!(live_bytes() - base, full - consumed);
+
+        // dropping the iterator half way frees everything else
+        drop(iter);
+        assert_eq!(live_bytes(), base);
+    }
+}
+
+#[test]
+fn next_back_allocates_only_for_map_backed_maps() {
+    let mut map: HashMap<usize, usize> = (0..16).map(|i| (i, i)).collect();
+    assert!(map.is_vec());
+    assert_eq!(allocations(|| assert_eq!(map.keys().rev().count(), 16)), 0);
+    assert_eq!(
+        allocations(|| assert_eq!(map.values_mut().rev().count(), 16)),
+        0
+    );
+
+    map.hint_large();
+    let mut iter = map.iter();
+    iter.next();
+    // the first call buffers what's left, later ones are served from it
+    assert_eq!(allocations(|| assert!(iter.next_back().is_some())), 1);
+    assert_eq!(allocations(|| assert_eq!(iter.rev().count(), 14)), 0);
+    assert_eq!(
+        allocations(|| assert_eq!(map.values_mut().rev().count(), 16)),
+        1
+    );
+}